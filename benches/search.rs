@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rs_scout::matrix;
+use rs_scout::sent_transform::{self, l2_normalize, IndexWithScore};
+use rs_scout::vector_index::{GuardedIndex, TextBody};
+use std::collections::BinaryHeap;
+
+const DIMENSIONS: usize = 512;
+const RESULTS: usize = 10;
+
+fn embeddings(rows: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..rows)
+        .map(|_| l2_normalize((0..DIMENSIONS).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+        .collect()
+}
+
+// The search before embeddings were stored as a flat matrix: one allocation
+// per row and a sequential scalar dot product.
+fn search_baseline(query: &[f32], embeddings: &[Vec<f32>], results: usize) -> Vec<IndexWithScore> {
+    // `IndexWithScore` orders by descending score, so the heap pops the lowest.
+    let mut heap: BinaryHeap<IndexWithScore> = BinaryHeap::new();
+
+    for (index, embedding) in embeddings.iter().enumerate() {
+        let score = matrix::dot_scalar(query, embedding);
+        heap.push(IndexWithScore { index, score });
+        if heap.len() > results {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+}
+
+fn bench_dot(c: &mut Criterion) {
+    let vectors = embeddings(2, 7);
+    let (a, b) = (&vectors[0], &vectors[1]);
+
+    let mut group = c.benchmark_group("dot");
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| matrix::dot_scalar(black_box(a), black_box(b)))
+    });
+    group.bench_function("simd", |bench| {
+        bench.iter(|| matrix::dot(black_box(a), black_box(b)))
+    });
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let query = embeddings(1, 1).remove(0);
+
+    let mut group = c.benchmark_group("search_knn");
+    group.sample_size(20);
+    for rows in [10_000, 100_000] {
+        let embeddings = embeddings(rows, 42);
+        let texts: Vec<TextBody> = (0..rows)
+            .map(|i| TextBody {
+                id: i.to_string(),
+                text: String::new(),
+                parent: None,
+            })
+            .collect();
+        let index = GuardedIndex::new(texts, embeddings.clone()).expect("Could not create index");
+        let flat = matrix::Matrix::from_rows(embeddings.clone()).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("baseline", rows), &rows, |bench, _| {
+            bench.iter(|| search_baseline(black_box(&query), &embeddings, RESULTS))
+        });
+        group.bench_with_input(BenchmarkId::new("rows_simd", rows), &rows, |bench, _| {
+            bench.iter(|| sent_transform::search_knn(black_box(&query), &embeddings, RESULTS))
+        });
+        group.bench_with_input(BenchmarkId::new("matrix_simd", rows), &rows, |bench, _| {
+            bench.iter(|| {
+                sent_transform::search_knn_matrix(
+                    black_box(&query),
+                    flat.as_slice(),
+                    DIMENSIONS,
+                    RESULTS,
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("index", rows), &rows, |bench, _| {
+            bench.iter(|| index.search_knn(black_box(&query), RESULTS))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dot, bench_search);
+criterion_main!(benches);
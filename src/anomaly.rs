@@ -0,0 +1,140 @@
+use crate::cluster::{self, KMeansParams};
+use crate::matrix;
+use crate::sent_transform;
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum AnomalyMethod {
+    Knn { k: usize },
+    Centroid { clusters: usize },
+}
+
+// The kNN method compares every pair of embeddings, so it is limited to
+// indexes of a moderate size. The centroid method scales to any size.
+pub const MAX_KNN_SIZE: usize = 10_000;
+
+// Mean cosine distance of every vector to its k nearest neighbours. This is
+// exact and compares every pair, so it is quadratic in the number of vectors;
+// rows are scored in parallel.
+pub fn knn_scores<V: AsRef<[f32]> + Sync>(vectors: &[V], k: usize) -> Result<Vec<f32>, String> {
+    if k == 0 || k >= vectors.len() {
+        return Err(format!(
+            "k must be between 1 and {} for {} embeddings",
+            vectors.len().saturating_sub(1),
+            vectors.len()
+        ));
+    }
+
+    if vectors.len() > MAX_KNN_SIZE {
+        return Err(format!(
+            "kNN anomaly scores support at most {MAX_KNN_SIZE} embeddings, got {}. Use the centroid method",
+            vectors.len()
+        ));
+    }
+
+    Ok(vectors
+        .par_iter()
+        .enumerate()
+        .map(|(i, vector)| {
+            // The vector itself scores lowest, so it is never among the k.
+            let nearest = sent_transform::top_k_rows(vectors.len(), k + 1, |j| {
+                if j == i {
+                    f32::NEG_INFINITY
+                } else {
+                    matrix::dot(vector.as_ref(), vectors[j].as_ref())
+                }
+            });
+            let mean: f32 = nearest.iter().take(k).map(|n| n.score).sum::<f32>() / k as f32;
+
+            1.0 - mean
+        })
+        .collect())
+}
+
+// Euclidean distance of every vector to the nearest centroid of a k-means
+// clustering.
+pub fn centroid_scores<V: AsRef<[f32]>>(
+    vectors: &[V],
+    clusters: usize,
+    params: &KMeansParams,
+) -> Result<Vec<f32>, String> {
+    let result = cluster::kmeans(vectors, clusters, params)?;
+
+    Ok(vectors
+        .iter()
+        .map(|vector| {
+            cluster::nearest_centroid(vector.as_ref(), &result.centroids)
+                .1
+                .sqrt()
+        })
+        .collect())
+}
+
+pub fn score<V: AsRef<[f32]> + Sync>(
+    vectors: &[V],
+    method: AnomalyMethod,
+    params: &KMeansParams,
+) -> Result<Vec<f32>, String> {
+    match method {
+        AnomalyMethod::Knn { k } => knn_scores(vectors, k),
+        AnomalyMethod::Centroid { clusters } => centroid_scores(vectors, clusters, params),
+    }
+}
+
+// Positions and scores of the n highest scores, most anomalous first.
+pub fn top_anomalies(scores: &[f32], n: usize) -> Vec<(usize, f32)> {
+    let mut ranked: Vec<(usize, f32)> = scores.iter().copied().enumerate().collect();
+    ranked.sort_by(|x, y| y.1.total_cmp(&x.1));
+    ranked.truncate(n);
+
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::KMeansMethod;
+
+    fn normalized(v: Vec<f32>) -> Vec<f32> {
+        let norm = matrix::dot(&v, &v).sqrt();
+        v.into_iter().map(|x| x / norm).collect()
+    }
+
+    fn vectors() -> Vec<Vec<f32>> {
+        vec![
+            normalized(vec![1.0, 0.0]),
+            normalized(vec![1.0, 0.1]),
+            normalized(vec![1.0, -0.1]),
+            normalized(vec![0.9, 0.05]),
+            normalized(vec![-0.2, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn test_knn_scores() {
+        let scores = knn_scores(&vectors(), 2).expect("knn_scores failed");
+
+        assert_eq!(top_anomalies(&scores, 1)[0].0, 4);
+        assert!(knn_scores(&vectors(), 5).is_err());
+        assert!(knn_scores(&vectors(), 0).is_err());
+        assert!(knn_scores(&vec![vec![1.0, 0.0]; MAX_KNN_SIZE + 1], 2).is_err());
+    }
+
+    #[test]
+    fn test_centroid_scores() {
+        let params = KMeansParams {
+            method: KMeansMethod::Full,
+            max_iter: 100,
+            tolerance: 1e-6,
+            seed: 0,
+        };
+        let scores = centroid_scores(&vectors(), 1, &params).expect("centroid_scores failed");
+
+        assert_eq!(top_anomalies(&scores, 1)[0].0, 4);
+    }
+
+    #[test]
+    fn test_top_anomalies() {
+        assert_eq!(top_anomalies(&[0.1, 0.7, 0.3], 2), vec![(1, 0.7), (2, 0.3)]);
+    }
+}
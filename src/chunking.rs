@@ -0,0 +1,201 @@
+use crate::vector_index::TextBody;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChunkStrategy {
+    Sentence,
+    TokenWindow { size: usize, overlap: usize },
+}
+
+impl ChunkStrategy {
+    pub fn token_window(size: usize, overlap: usize) -> Result<ChunkStrategy, String> {
+        if size == 0 {
+            return Err(String::from("Chunk size must be greater than 0"));
+        }
+
+        if overlap >= size {
+            return Err(format!(
+                "Chunk overlap ({overlap}) must be smaller than chunk size ({size})"
+            ));
+        }
+
+        Ok(ChunkStrategy::TokenWindow { size, overlap })
+    }
+}
+
+const SENTENCE_TERMINATORS: [char; 6] = ['.', '!', '?', '。', '！', '？'];
+
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+
+        let at_boundary = match chars.peek() {
+            Some(next) => next.is_whitespace(),
+            None => true,
+        };
+        if SENTENCE_TERMINATORS.contains(&c) && at_boundary {
+            sentences.push(current.trim().to_string());
+            current.clear();
+        }
+    }
+    sentences.push(current.trim().to_string());
+
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+// Windows are sized in model tokens rather than words, since the model
+// truncates inputs by wordpieces. `count` gives the number of wordpieces of
+// each word. A window holds at most `size` of them, except for a single word
+// longer than that, and the next window repeats at most `overlap` of them,
+// as long as it still has room for the word after this one.
+fn split_token_windows<F>(text: &str, size: usize, overlap: usize, count: F) -> Vec<String>
+where
+    F: Fn(&[&str]) -> Vec<usize>,
+{
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let tokens = count(&words);
+
+    let mut windows = vec![];
+    let mut start = 0;
+    while start < words.len() {
+        let mut end = start + 1;
+        let mut length = tokens[start];
+        while end < words.len() && length + tokens[end] <= size {
+            length += tokens[end];
+            end += 1;
+        }
+        windows.push(words[start..end].join(" "));
+
+        if end == words.len() {
+            break;
+        }
+
+        let mut next = end;
+        let mut repeated = 0;
+        while next > start + 1
+            && repeated + tokens[next - 1] <= overlap
+            && repeated + tokens[next - 1] + tokens[end] <= size
+        {
+            repeated += tokens[next - 1];
+            next -= 1;
+        }
+        start = next;
+    }
+
+    windows
+}
+
+pub fn chunk_id(parent: &str, chunk: usize) -> String {
+    format!("{parent}#{chunk}")
+}
+
+// Documents without any text produce no chunks, so they are rejected rather
+// than silently dropped.
+pub fn chunk_text_bodies<F>(
+    text_bodies: &[TextBody],
+    strategy: ChunkStrategy,
+    count: F,
+) -> Result<Vec<TextBody>, String>
+where
+    F: Fn(&[&str]) -> Vec<usize>,
+{
+    let mut chunked = vec![];
+    for text_body in text_bodies {
+        let chunks = match strategy {
+            ChunkStrategy::Sentence => split_sentences(&text_body.text),
+            ChunkStrategy::TokenWindow { size, overlap } => {
+                split_token_windows(&text_body.text, size, overlap, &count)
+            }
+        };
+        if chunks.is_empty() {
+            return Err(format!("{} has no text to chunk", text_body.id));
+        }
+
+        chunked.extend(chunks.into_iter().enumerate().map(|(idx, text)| TextBody {
+            id: chunk_id(&text_body.id, idx),
+            text,
+            parent: Some(text_body.id.clone()),
+        }));
+    }
+
+    Ok(chunked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sentences() {
+        let sentences = split_sentences("The roof is new. Is the deck 3.5m wide?  Yes!  ");
+
+        assert_eq!(
+            sentences,
+            vec!["The roof is new.", "Is the deck 3.5m wide?", "Yes!"]
+        );
+    }
+
+    fn one_per_word(words: &[&str]) -> Vec<usize> {
+        vec![1; words.len()]
+    }
+
+    #[test]
+    fn test_split_token_windows() {
+        let windows = split_token_windows("a b c d e f g", 3, 1, one_per_word);
+
+        assert_eq!(windows, vec!["a b c", "c d e", "e f g"]);
+        assert_eq!(split_token_windows("a b", 3, 1, one_per_word), vec!["a b"]);
+        assert!(split_token_windows("   ", 3, 1, one_per_word).is_empty());
+    }
+
+    #[test]
+    fn test_split_token_windows_counts_wordpieces() {
+        let by_length = |words: &[&str]| words.iter().map(|w| w.len()).collect::<Vec<usize>>();
+        let windows = split_token_windows("aa b cccc dd e", 4, 1, by_length);
+
+        assert_eq!(windows, vec!["aa b", "cccc", "dd e"]);
+    }
+
+    #[test]
+    fn test_token_window_validation() {
+        assert!(ChunkStrategy::token_window(0, 0).is_err());
+        assert!(ChunkStrategy::token_window(4, 4).is_err());
+        assert_eq!(
+            ChunkStrategy::token_window(4, 1),
+            Ok(ChunkStrategy::TokenWindow {
+                size: 4,
+                overlap: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_chunk_text_bodies() {
+        let text_bodies = vec![TextBody {
+            id: "doc".to_string(),
+            text: "First sentence. Second sentence.".to_string(),
+            parent: None,
+        }];
+
+        let chunks = chunk_text_bodies(&text_bodies, ChunkStrategy::Sentence, one_per_word)
+            .expect("Could not chunk");
+        let ids: Vec<&str> = chunks.iter().map(|c| c.id.as_str()).collect();
+
+        assert_eq!(ids, vec!["doc#0", "doc#1"]);
+        assert!(chunks.iter().all(|c| c.parent.as_deref() == Some("doc")));
+
+        let empty = vec![TextBody {
+            id: "empty".to_string(),
+            text: "  ".to_string(),
+            parent: None,
+        }];
+        assert_eq!(
+            chunk_text_bodies(&empty, ChunkStrategy::Sentence, one_per_word).unwrap_err(),
+            "empty has no text to chunk"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use crate::matrix::dot;
+use crate::names::valid_name;
+use liblinear::util::{PredictionInput, TrainingInput};
+use liblinear::{Builder, LibLinearModel, Serializer, SolverType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClassifierInfo {
+    pub name: String,
+    pub labels: Vec<String>,
+    pub examples: usize,
+    pub dimensions: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LabelProbability {
+    pub label: String,
+    pub probability: f32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Classification {
+    pub label: String,
+    pub probabilities: Vec<LabelProbability>,
+}
+
+const DEFAULT_COST: f64 = 1.0;
+
+fn features(embedding: &[f32]) -> Vec<f64> {
+    embedding.iter().map(|x| *x as f64).collect()
+}
+
+// Classifiers are multi-class logistic regression models, since liblinear
+// only estimates probabilities for its logistic regression solvers. Each
+// model is saved to `{dir}/{name}.model` with its metadata in
+// `{dir}/{name}.json`. Models are loaded once and shared by concurrent
+// classifications.
+pub struct ClassifierRegistry {
+    dir: PathBuf,
+    classifiers: RwLock<HashMap<String, Arc<Classifier>>>,
+}
+
+struct Classifier {
+    info: ClassifierInfo,
+    model: SharedModel,
+}
+
+// The model types returned by `Builder::build_model` and
+// `Serializer::load_model` cannot be named, so models are boxed.
+struct SharedModel(Box<dyn LibLinearModel>);
+
+// Safety: the model wraps a pointer to a C `struct model` that is only
+// written while training. Predictions pass it to liblinear as a
+// `const struct model *` and write to buffers allocated per call, and the
+// model is freed by whichever thread drops the last reference. Once loaded,
+// the model is never mutated, so sharing it across threads does not race.
+unsafe impl Send for SharedModel {}
+unsafe impl Sync for SharedModel {}
+
+impl ClassifierRegistry {
+    pub fn open(dir: &str) -> Result<ClassifierRegistry, String> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Could not create classifier directory: {err}"))?;
+
+        let mut classifiers = HashMap::new();
+        let entries =
+            fs::read_dir(&dir).map_err(|err| format!("Could not read classifiers: {err}"))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                let info = fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| {
+                        serde_json::from_str::<ClassifierInfo>(&json).map_err(|err| err.to_string())
+                    })
+                    .map_err(|err| format!("Could not load {}: {err}", path.display()))?;
+                let model_path = path.with_extension("model");
+                let model = Serializer::load_model(&model_path.to_string_lossy())
+                    .map_err(|err| format!("Could not load {}: {err}", model_path.display()))?;
+                classifiers.insert(
+                    info.name.clone(),
+                    Arc::new(Classifier {
+                        info,
+                        model: SharedModel(Box::new(model)),
+                    }),
+                );
+            }
+        }
+
+        Ok(ClassifierRegistry {
+            dir,
+            classifiers: RwLock::new(classifiers),
+        })
+    }
+
+    fn model_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.model"))
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<ClassifierInfo> {
+        self.classifiers
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|classifier| classifier.info.clone())
+    }
+
+    pub fn train(
+        &self,
+        name: &str,
+        examples: &[(String, Vec<f32>)],
+        cost: Option<f64>,
+    ) -> Result<ClassifierInfo, String> {
+        valid_name("classifier", name)?;
+
+        let mut labels: Vec<String> = vec![];
+        for (label, _) in examples {
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+        if labels.len() < 2 {
+            return Err(String::from(
+                "Training a classifier requires examples of at least 2 labels",
+            ));
+        }
+
+        let dimensions = examples[0].1.len();
+        if examples.iter().any(|(_, e)| e.len() != dimensions) {
+            return Err(String::from(
+                "All training examples must have the same dimensions",
+            ));
+        }
+
+        let targets: Vec<f64> = examples
+            .iter()
+            .map(|(label, _)| labels.iter().position(|l| l == label).unwrap() as f64)
+            .collect();
+        let inputs: Vec<Vec<f64>> = examples.iter().map(|(_, e)| features(e)).collect();
+
+        let mut model_builder = Builder::new();
+        model_builder.problem().input_data(
+            TrainingInput::from_dense_features(targets, inputs)
+                .map_err(|err| format!("Invalid training input: {err}"))?,
+        );
+        model_builder
+            .parameters()
+            .solver_type(SolverType::L2R_LR)
+            .stopping_criterion(0.01)
+            .constraints_violation_cost(cost.unwrap_or(DEFAULT_COST));
+
+        let model = model_builder
+            .build_model()
+            .map_err(|err| format!("Error training classifier: {err}"))?;
+
+        let info = ClassifierInfo {
+            name: String::from(name),
+            labels,
+            examples: examples.len(),
+            dimensions,
+        };
+
+        let mut classifiers = self.classifiers.write().unwrap();
+        model
+            .save_to_disk(&self.model_path(name).to_string_lossy())
+            .map_err(|err| format!("Could not save classifier: {err}"))?;
+        serde_json::to_string(&info)
+            .map_err(|err| err.to_string())
+            .and_then(|json| fs::write(self.info_path(name), json).map_err(|err| err.to_string()))
+            .map_err(|err| format!("Could not save classifier: {err}"))?;
+        classifiers.insert(
+            String::from(name),
+            Arc::new(Classifier {
+                info: info.clone(),
+                model: SharedModel(Box::new(model)),
+            }),
+        );
+
+        Ok(info)
+    }
+
+    pub fn classify(
+        &self,
+        name: &str,
+        embeddings: &[Vec<f32>],
+    ) -> Result<Vec<Classification>, String> {
+        let classifier = self
+            .classifiers
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Classifier {name} not found"))?;
+        let info = &classifier.info;
+        let model = &classifier.model.0;
+
+        embeddings
+            .iter()
+            .map(|embedding| {
+                if embedding.len() != info.dimensions {
+                    return Err(format!(
+                        "Classifier {name} expects {} dimensions, got {}",
+                        info.dimensions,
+                        embedding.len()
+                    ));
+                }
+
+                let input = PredictionInput::from_dense_features(features(embedding))
+                    .map_err(|err| format!("Invalid prediction input: {err}"))?;
+                let (probabilities, _) = model
+                    .predict_probabilities(input)
+                    .map_err(|err| format!("Could not classify: {err}"))?;
+
+                Ok(label_probabilities(
+                    &info.labels,
+                    model.labels(),
+                    &probabilities,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn delete(&self, name: &str) -> Option<ClassifierInfo> {
+        let mut classifiers = self.classifiers.write().unwrap();
+        let info = classifiers.remove(name)?.info.clone();
+
+        for path in [self.model_path(name), self.info_path(name)] {
+            if let Err(err) = fs::remove_file(&path) {
+                log::warn!("Could not remove {}: {err}", path.display());
+            }
+        }
+
+        Some(info)
+    }
+}
+
+fn ranked(mut probabilities: Vec<LabelProbability>) -> Classification {
+    probabilities.sort_by(|x, y| y.probability.total_cmp(&x.probability));
+
+    Classification {
+        label: probabilities
+            .first()
+            .map_or(String::new(), |p| p.label.clone()),
+        probabilities,
+    }
+}
+
+// liblinear orders probabilities by its own label order, which follows the
+// order labels first appear in the training data.
+fn label_probabilities(
+    label_names: &[String],
+    model_labels: &[i32],
+    probabilities: &[f64],
+) -> Classification {
+    let probabilities: Vec<LabelProbability> = model_labels
+        .iter()
+        .zip(probabilities.iter())
+        .map(|(label, probability)| LabelProbability {
+            label: label_names[*label as usize].clone(),
+            probability: *probability as f32,
+        })
+        .collect();
+
+    ranked(probabilities)
+}
+
+// Scores each text against every label by the cosine similarity of their
+// normalized embeddings, then turns the scores into a distribution with a
+// softmax. Cosine similarities fall in a narrow range, so they are divided by
+// a small temperature to make the distribution usefully peaked.
+pub fn zero_shot(
+    labels: &[String],
+    label_embeddings: &[Vec<f32>],
+    text_embeddings: &[Vec<f32>],
+    temperature: f32,
+) -> Vec<Classification> {
+    text_embeddings
+        .iter()
+        .map(|text| {
+            let logits: Vec<f32> = label_embeddings
+                .iter()
+                .map(|label| dot(text, label) / temperature)
+                .collect();
+
+            let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let exps: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+            let total: f32 = exps.iter().sum();
+
+            let probabilities: Vec<LabelProbability> = labels
+                .iter()
+                .zip(exps)
+                .map(|(label, exp)| LabelProbability {
+                    label: label.clone(),
+                    probability: exp / total,
+                })
+                .collect();
+
+            ranked(probabilities)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_registry(name: &str) -> ClassifierRegistry {
+        let dir = std::env::temp_dir().join(format!("scout-classifiers-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+
+        ClassifierRegistry::open(&dir.to_string_lossy()).expect("Could not open registry")
+    }
+
+    #[test]
+    fn test_label_probabilities() {
+        let labels = vec![String::from("a"), String::from("b"), String::from("c")];
+        let classification = label_probabilities(&labels, &[2, 0, 1], &[0.2, 0.1, 0.7]);
+
+        assert_eq!(classification.label, "b");
+        assert_eq!(
+            classification
+                .probabilities
+                .iter()
+                .map(|p| p.label.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["b", "c", "a"]
+        );
+    }
+
+    #[test]
+    fn test_zero_shot() {
+        let labels = vec![String::from("price"), String::from("condition")];
+        let classifications = zero_shot(
+            &labels,
+            &[vec![1.0, 0.0], vec![0.0, 1.0]],
+            &[vec![0.8, 0.6], vec![0.0, 1.0]],
+            0.05,
+        );
+
+        assert_eq!(classifications[0].label, "price");
+        assert_eq!(classifications[1].label, "condition");
+
+        let probabilities = &classifications[0].probabilities;
+        assert!((probabilities[0].probability + probabilities[1].probability - 1.0).abs() < 1e-6);
+        assert!(probabilities[0].probability > 0.95);
+    }
+
+    #[test]
+    fn test_train_validation() {
+        let registry = temp_registry("validation");
+        let one_label = vec![
+            (String::from("a"), vec![1.0, 0.0]),
+            (String::from("a"), vec![0.9, 0.1]),
+        ];
+
+        assert!(registry.train("../escape", &one_label, None).is_err());
+        assert_eq!(
+            registry.train("one", &one_label, None).unwrap_err(),
+            "Training a classifier requires examples of at least 2 labels"
+        );
+        assert!(registry.classify("missing", &[vec![1.0, 0.0]]).is_err());
+    }
+
+    #[test]
+    fn test_train_classify_and_reload() {
+        let registry = temp_registry("roundtrip");
+        let examples: Vec<(String, Vec<f32>)> = vec![
+            ("price", vec![1.0, 0.0]),
+            ("price", vec![0.9, 0.1]),
+            ("condition", vec![0.0, 1.0]),
+            ("condition", vec![0.1, 0.9]),
+        ]
+        .into_iter()
+        .map(|(label, embedding)| (String::from(label), embedding))
+        .collect();
+
+        let info = registry
+            .train("sections", &examples, Some(10.0))
+            .expect("Could not train");
+        assert_eq!(info.labels, vec!["price", "condition"]);
+
+        let reopened = ClassifierRegistry::open(&registry.dir.to_string_lossy())
+            .expect("Could not reopen registry");
+        let classifications = reopened
+            .classify("sections", &[vec![0.95, 0.05], vec![0.05, 0.95]])
+            .expect("Could not classify");
+
+        assert_eq!(classifications[0].label, "price");
+        assert_eq!(classifications[1].label, "condition");
+        assert!(reopened.delete("sections").is_some());
+        assert!(reopened.get("sections").is_none());
+    }
+}
@@ -0,0 +1,764 @@
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KMeansMethod {
+    Full,
+    MiniBatch { batch_size: usize },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct KMeansParams {
+    pub method: KMeansMethod,
+    pub max_iter: usize,
+    pub tolerance: f32,
+    pub seed: u64,
+}
+
+#[derive(Debug)]
+pub struct KMeansResult {
+    pub centroids: Vec<Vec<f32>>,
+    pub assignments: Vec<usize>,
+    pub iterations: usize,
+    pub inertia: f32,
+}
+
+pub fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .fold(0.0, |sum, (ae, be)| sum + (ae - be) * (ae - be))
+}
+
+pub fn nearest_centroid(vector: &[f32], centroids: &[Vec<f32>]) -> (usize, f32) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(idx, centroid)| (idx, squared_distance(vector, centroid)))
+        .fold((0, f32::INFINITY), |best, candidate| {
+            if candidate.1 < best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+}
+
+fn validate<V: AsRef<[f32]>>(vectors: &[V], k: usize) -> Result<(), String> {
+    if k == 0 {
+        return Err(String::from("k must be greater than 0"));
+    }
+
+    if vectors.len() < k {
+        return Err(format!(
+            "Cannot create {k} clusters from {} embeddings",
+            vectors.len()
+        ));
+    }
+
+    let dim = vectors[0].as_ref().len();
+    match vectors.iter().position(|v| v.as_ref().len() != dim) {
+        Some(idx) => Err(format!(
+            "Embedding {idx} has dimension {} (expected {dim})",
+            vectors[idx].as_ref().len()
+        )),
+        None => Ok(()),
+    }
+}
+
+// k-means++ seeding: each further centroid is sampled with probability
+// proportional to its squared distance from the closest centroid so far.
+fn init_centroids<V: AsRef<[f32]>>(vectors: &[V], k: usize, rng: &mut StdRng) -> Vec<Vec<f32>> {
+    let mut centroids = vec![vectors[rng.gen_range(0..vectors.len())].as_ref().to_vec()];
+    let mut dists: Vec<f32> = vectors
+        .iter()
+        .map(|v| squared_distance(v.as_ref(), &centroids[0]))
+        .collect();
+
+    while centroids.len() < k {
+        let total: f32 = dists.iter().sum();
+        let next = if total > 0.0 {
+            let mut target = rng.gen_range(0.0..total);
+            dists
+                .iter()
+                .position(|d| {
+                    target -= d;
+                    target < 0.0
+                })
+                .unwrap_or(vectors.len() - 1)
+        } else {
+            rng.gen_range(0..vectors.len())
+        };
+
+        centroids.push(vectors[next].as_ref().to_vec());
+        for (dist, vector) in dists.iter_mut().zip(vectors.iter()) {
+            *dist = dist.min(squared_distance(
+                vector.as_ref(),
+                &centroids[centroids.len() - 1],
+            ));
+        }
+    }
+
+    centroids
+}
+
+fn assign<V: AsRef<[f32]>>(vectors: &[V], centroids: &[Vec<f32>]) -> (Vec<usize>, Vec<f32>) {
+    vectors
+        .iter()
+        .map(|vector| nearest_centroid(vector.as_ref(), centroids))
+        .unzip()
+}
+
+fn lloyd<V: AsRef<[f32]>>(
+    vectors: &[V],
+    centroids: &mut [Vec<f32>],
+    params: &KMeansParams,
+) -> usize {
+    let dim = vectors[0].as_ref().len();
+
+    for iteration in 1..=params.max_iter {
+        let (assignments, mut dists) = assign(vectors, centroids);
+
+        let mut sums = vec![vec![0.0_f32; dim]; centroids.len()];
+        let mut counts = vec![0_usize; centroids.len()];
+        for (vector, cluster) in vectors.iter().zip(assignments.iter()) {
+            counts[*cluster] += 1;
+            for (sum, elem) in sums[*cluster].iter_mut().zip(vector.as_ref().iter()) {
+                *sum += elem;
+            }
+        }
+
+        let mut shift = 0.0;
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let updated: Vec<f32> = if counts[cluster] > 0 {
+                sums[cluster]
+                    .iter()
+                    .map(|sum| sum / counts[cluster] as f32)
+                    .collect()
+            } else {
+                // Re-seed an empty cluster with the point farthest from its
+                // centroid. That point is then marked as taken, so several
+                // empty clusters are not re-seeded with the same point.
+                let farthest =
+                    dists.iter().enumerate().fold(
+                        0,
+                        |best, (idx, d)| if *d > dists[best] { idx } else { best },
+                    );
+                dists[farthest] = 0.0;
+                vectors[farthest].as_ref().to_vec()
+            };
+
+            shift += squared_distance(centroid, &updated);
+            *centroid = updated;
+        }
+
+        if shift <= params.tolerance {
+            return iteration;
+        }
+    }
+
+    params.max_iter
+}
+
+// Mini-batch k-means (Sculley, 2010): centroids are moved towards the points
+// of a random batch with a per-centroid learning rate of 1 / count.
+fn minibatch<V: AsRef<[f32]>>(
+    vectors: &[V],
+    centroids: &mut [Vec<f32>],
+    batch_size: usize,
+    params: &KMeansParams,
+    rng: &mut StdRng,
+) -> usize {
+    let batch_size = batch_size.clamp(1, vectors.len());
+    let mut counts = vec![0_usize; centroids.len()];
+
+    for iteration in 1..=params.max_iter {
+        let batch = sample(rng, vectors.len(), batch_size);
+        let previous = centroids.to_vec();
+
+        for idx in batch.iter() {
+            let (cluster, _) = nearest_centroid(vectors[idx].as_ref(), centroids);
+            counts[cluster] += 1;
+
+            let rate = 1.0 / counts[cluster] as f32;
+            for (c, x) in centroids[cluster]
+                .iter_mut()
+                .zip(vectors[idx].as_ref().iter())
+            {
+                *c += rate * (x - *c);
+            }
+        }
+
+        let shift: f32 = previous
+            .iter()
+            .zip(centroids.iter())
+            .map(|(before, after)| squared_distance(before, after))
+            .sum();
+
+        if shift <= params.tolerance {
+            return iteration;
+        }
+    }
+
+    params.max_iter
+}
+
+pub fn kmeans<V: AsRef<[f32]>>(
+    vectors: &[V],
+    k: usize,
+    params: &KMeansParams,
+) -> Result<KMeansResult, String> {
+    validate(vectors, k)?;
+
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let mut centroids = init_centroids(vectors, k, &mut rng);
+
+    let iterations = match params.method {
+        KMeansMethod::Full => lloyd(vectors, &mut centroids, params),
+        KMeansMethod::MiniBatch { batch_size } => {
+            minibatch(vectors, &mut centroids, batch_size, params, &mut rng)
+        }
+    };
+
+    let (assignments, dists) = assign(vectors, &centroids);
+
+    Ok(KMeansResult {
+        centroids,
+        assignments,
+        iterations,
+        inertia: dists.iter().sum(),
+    })
+}
+
+// Index of the member closest to each centroid, or None for an empty cluster.
+pub fn representatives<V: AsRef<[f32]>>(
+    vectors: &[V],
+    centroids: &[Vec<f32>],
+    assignments: &[usize],
+) -> Vec<Option<usize>> {
+    let mut best: Vec<Option<(usize, f32)>> = vec![None; centroids.len()];
+
+    for (idx, (vector, cluster)) in vectors.iter().zip(assignments.iter()).enumerate() {
+        let dist = squared_distance(vector.as_ref(), &centroids[*cluster]);
+        match best[*cluster] {
+            Some((_, best_dist)) if best_dist <= dist => (),
+            _ => best[*cluster] = Some((idx, dist)),
+        }
+    }
+
+    best.into_iter().map(|b| b.map(|(idx, _)| idx)).collect()
+}
+
+pub fn cluster_sizes(assignments: &[usize], k: usize) -> Vec<usize> {
+    let mut sizes = vec![0; k];
+    for cluster in assignments {
+        sizes[*cluster] += 1;
+    }
+
+    sizes
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Linkage {
+    Single,
+    Complete,
+    Average,
+    Ward,
+}
+
+// One step of a dendrogram, in the same layout as scipy's linkage matrix:
+// leaves are numbered 0..n and the node created by merge i is numbered n + i.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Merge {
+    pub left: usize,
+    pub right: usize,
+    pub distance: f32,
+    pub size: usize,
+}
+
+pub fn find_root(parents: &mut [usize], node: usize) -> usize {
+    let mut root = node;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    let mut node = node;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+
+    root
+}
+
+// The full distance matrix is kept in memory, so hierarchical clustering is
+// limited to indexes of a moderate size.
+pub const MAX_HIERARCHICAL_SIZE: usize = 5_000;
+
+// Agglomerative clustering using the nearest-neighbor chain algorithm, which
+// runs in O(n^2) time for the supported (reducible) linkages. Distances are
+// Euclidean; Ward's method is updated on squared distances.
+pub fn agglomerative<V: AsRef<[f32]>>(
+    vectors: &[V],
+    linkage: Linkage,
+) -> Result<Vec<Merge>, String> {
+    validate(vectors, 1)?;
+
+    if vectors.len() > MAX_HIERARCHICAL_SIZE {
+        return Err(format!(
+            "Hierarchical clustering supports at most {MAX_HIERARCHICAL_SIZE} embeddings, got {}",
+            vectors.len()
+        ));
+    }
+
+    let n = vectors.len();
+    let mut dist = vec![0.0_f32; n * n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let d = match linkage {
+                Linkage::Ward => squared_distance(vectors[i].as_ref(), vectors[j].as_ref()),
+                _ => squared_distance(vectors[i].as_ref(), vectors[j].as_ref()).sqrt(),
+            };
+            dist[i * n + j] = d;
+            dist[j * n + i] = d;
+        }
+    }
+
+    let mut sizes = vec![1_usize; n];
+    let mut active = vec![true; n];
+    let mut chain: Vec<usize> = vec![];
+    let mut raw_merges: Vec<(usize, usize, f32)> = vec![];
+
+    while raw_merges.len() < n - 1 {
+        if chain.is_empty() {
+            chain.push(active.iter().position(|a| *a).unwrap());
+        }
+
+        let a = chain[chain.len() - 1];
+        let prev = if chain.len() >= 2 {
+            Some(chain[chain.len() - 2])
+        } else {
+            None
+        };
+
+        // Ties are broken in favour of the previous element of the chain,
+        // which guarantees that the chain terminates.
+        let mut nearest = prev;
+        let mut nearest_dist = prev.map_or(f32::INFINITY, |p| dist[a * n + p]);
+        for b in 0..n {
+            if b != a && active[b] && dist[a * n + b] < nearest_dist {
+                nearest = Some(b);
+                nearest_dist = dist[a * n + b];
+            }
+        }
+        let b = nearest.unwrap();
+
+        if Some(b) != prev {
+            chain.push(b);
+            continue;
+        }
+
+        chain.truncate(chain.len() - 2);
+        raw_merges.push((a, b, nearest_dist));
+
+        let (na, nb) = (sizes[a] as f32, sizes[b] as f32);
+        for k in 0..n {
+            if !active[k] || k == a || k == b {
+                continue;
+            }
+
+            let (dak, dbk) = (dist[a * n + k], dist[b * n + k]);
+            let nk = sizes[k] as f32;
+            let d = match linkage {
+                Linkage::Single => dak.min(dbk),
+                Linkage::Complete => dak.max(dbk),
+                Linkage::Average => (na * dak + nb * dbk) / (na + nb),
+                Linkage::Ward => {
+                    ((na + nk) * dak + (nb + nk) * dbk - nk * nearest_dist) / (na + nb + nk)
+                }
+            };
+            dist[a * n + k] = d;
+            dist[k * n + a] = d;
+        }
+
+        active[b] = false;
+        sizes[a] += sizes[b];
+    }
+
+    // The chain finds merges out of order, so sort them by distance and
+    // relabel clusters with a union-find over the leaves.
+    raw_merges.sort_by(|x, y| x.2.total_cmp(&y.2));
+
+    let mut parents: Vec<usize> = (0..n).collect();
+    let mut nodes: Vec<usize> = (0..n).collect();
+    let mut node_sizes = vec![1_usize; n];
+
+    Ok(raw_merges
+        .into_iter()
+        .enumerate()
+        .map(|(step, (a, b, d))| {
+            let (root_a, root_b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+            let (left, right) = (nodes[root_a], nodes[root_b]);
+            let size = node_sizes[root_a] + node_sizes[root_b];
+
+            parents[root_b] = root_a;
+            nodes[root_a] = n + step;
+            node_sizes[root_a] = size;
+
+            Merge {
+                left: left.min(right),
+                right: left.max(right),
+                distance: match linkage {
+                    Linkage::Ward => d.max(0.0).sqrt(),
+                    _ => d,
+                },
+                size,
+            }
+        })
+        .collect())
+}
+
+// Flat clusters obtained by stopping the dendrogram at k clusters. Clusters
+// are numbered by the order in which their first leaf appears.
+pub fn cut_dendrogram(merges: &[Merge], n: usize, k: usize) -> Vec<usize> {
+    let mut parents: Vec<usize> = (0..(2 * n).max(1) - 1).collect();
+    for (step, merge) in merges.iter().take(n.saturating_sub(k)).enumerate() {
+        parents[merge.left] = n + step;
+        parents[merge.right] = n + step;
+    }
+
+    let mut labels: Vec<Option<usize>> = vec![None; parents.len()];
+    let mut next_label = 0;
+
+    (0..n)
+        .map(|leaf| {
+            let root = find_root(&mut parents, leaf);
+            *labels[root].get_or_insert_with(|| {
+                next_label += 1;
+                next_label - 1
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClusterCount {
+    Fixed(usize),
+    Auto { min: usize, max: usize },
+}
+
+pub const SILHOUETTE_SAMPLE_SIZE: usize = 1_000;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct KScore {
+    pub k: usize,
+    pub silhouette: f32,
+}
+
+// Mean silhouette coefficient. For large inputs it is estimated, like
+// sklearn's `sample_size`, on a random sample of `sample_size` points that
+// are only compared with each other, so the cost does not grow with n. The
+// same seed draws the same sample, so scores for different k are comparable.
+pub fn silhouette<V: AsRef<[f32]>>(
+    vectors: &[V],
+    assignments: &[usize],
+    sample_size: usize,
+    seed: u64,
+) -> f32 {
+    let sampled: Vec<usize> = if vectors.len() > sample_size {
+        let mut rng = StdRng::seed_from_u64(seed);
+        sample(&mut rng, vectors.len(), sample_size).into_vec()
+    } else {
+        (0..vectors.len()).collect()
+    };
+    if sampled.is_empty() {
+        return 0.0;
+    }
+
+    let k = assignments.iter().max().map_or(0, |max| max + 1);
+    let labels: Vec<usize> = sampled.iter().map(|i| assignments[*i]).collect();
+    let sizes = cluster_sizes(&labels, k);
+
+    let total: f32 = sampled
+        .iter()
+        .zip(labels.iter())
+        .map(|(i, own)| {
+            if sizes[*own] <= 1 {
+                return 0.0;
+            }
+
+            let mut sums = vec![0.0_f32; k];
+            for (j, label) in sampled.iter().zip(labels.iter()) {
+                sums[*label] += squared_distance(vectors[*i].as_ref(), vectors[*j].as_ref()).sqrt();
+            }
+
+            let a = sums[*own] / (sizes[*own] - 1) as f32;
+            let b = (0..k)
+                .filter(|c| c != own && sizes[*c] > 0)
+                .map(|c| sums[c] / sizes[c] as f32)
+                .fold(f32::INFINITY, f32::min);
+
+            if b.is_infinite() {
+                0.0
+            } else {
+                (b - a) / a.max(b)
+            }
+        })
+        .sum();
+
+    total / sampled.len() as f32
+}
+
+fn validate_k_range(n: usize, k_min: usize, k_max: usize) -> Result<(), String> {
+    if k_min < 2 || k_min > k_max {
+        return Err(format!(
+            "Invalid k range {k_min}..={k_max}: need 2 <= k_min <= k_max"
+        ));
+    }
+
+    if k_max >= n {
+        return Err(format!(
+            "k_max ({k_max}) must be smaller than the number of embeddings ({n})"
+        ));
+    }
+
+    Ok(())
+}
+
+// Picks the k in k_min..=k_max whose clustering has the highest silhouette,
+// and returns it along with that clustering so it need not be recomputed.
+pub fn select_k<V, R, F, A>(
+    vectors: &[V],
+    k_min: usize,
+    k_max: usize,
+    sample_size: usize,
+    seed: u64,
+    mut cluster_for: F,
+    assignments_of: A,
+) -> Result<(usize, Vec<KScore>, R), String>
+where
+    V: AsRef<[f32]>,
+    F: FnMut(usize) -> Result<R, String>,
+    A: Fn(&R) -> &[usize],
+{
+    validate_k_range(vectors.len(), k_min, k_max)?;
+
+    let mut scores: Vec<KScore> = vec![];
+    let mut best: Option<(usize, f32, R)> = None;
+    for k in k_min..=k_max {
+        let clustering = cluster_for(k)?;
+        let score = silhouette(vectors, assignments_of(&clustering), sample_size, seed);
+        scores.push(KScore {
+            k,
+            silhouette: score,
+        });
+
+        let better = match &best {
+            Some((_, best_score, _)) => score > *best_score,
+            None => true,
+        };
+        if better {
+            best = Some((k, score, clustering));
+        }
+    }
+
+    let (best, _, clustering) = best.expect("k range is not empty");
+    Ok((best, scores, clustering))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blobs() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 0.0],
+            vec![0.9, 0.1],
+            vec![0.95, -0.05],
+            vec![-1.0, 0.0],
+            vec![-0.9, 0.1],
+            vec![-0.95, -0.1],
+        ]
+    }
+
+    fn params(method: KMeansMethod) -> KMeansParams {
+        KMeansParams {
+            method,
+            max_iter: 100,
+            tolerance: 1e-6,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn test_kmeans_separates_blobs() {
+        let result = kmeans(&blobs(), 2, &params(KMeansMethod::Full)).expect("kmeans failed");
+        let a = &result.assignments;
+
+        assert!(a[0] == a[1] && a[1] == a[2]);
+        assert!(a[3] == a[4] && a[4] == a[5]);
+        assert_ne!(a[0], a[3]);
+        assert_eq!(cluster_sizes(a, 2), vec![3, 3]);
+    }
+
+    #[test]
+    fn test_lloyd_reseeds_empty_clusters_apart() {
+        let vectors = vec![
+            vec![0.0, 0.0],
+            vec![0.0, 0.0],
+            vec![10.0, 0.0],
+            vec![11.0, 0.0],
+        ];
+        let mut centroids = vec![vec![0.0, 0.0], vec![100.0, 100.0], vec![200.0, 200.0]];
+
+        lloyd(&vectors, &mut centroids, &params(KMeansMethod::Full));
+
+        let (assignments, _) = assign(&vectors, &centroids);
+        assert_eq!(cluster_sizes(&assignments, 3), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_minibatch_separates_blobs() {
+        let method = KMeansMethod::MiniBatch { batch_size: 4 };
+        let result = kmeans(&blobs(), 2, &params(method)).expect("kmeans failed");
+        let a = &result.assignments;
+
+        assert_ne!(a[0], a[3]);
+        assert_eq!(cluster_sizes(a, 2), vec![3, 3]);
+    }
+
+    #[test]
+    fn test_kmeans_is_deterministic_for_a_seed() {
+        let first = kmeans(&blobs(), 2, &params(KMeansMethod::Full)).expect("kmeans failed");
+        let second = kmeans(&blobs(), 2, &params(KMeansMethod::Full)).expect("kmeans failed");
+
+        assert_eq!(first.assignments, second.assignments);
+    }
+
+    #[test]
+    fn test_kmeans_validation() {
+        assert_eq!(
+            kmeans(&blobs(), 7, &params(KMeansMethod::Full)).unwrap_err(),
+            "Cannot create 7 clusters from 6 embeddings"
+        );
+
+        let mismatched = vec![vec![1.0, 0.0], vec![1.0]];
+        assert_eq!(
+            kmeans(&mismatched, 2, &params(KMeansMethod::Full)).unwrap_err(),
+            "Embedding 1 has dimension 1 (expected 2)"
+        );
+    }
+
+    #[test]
+    fn test_agglomerative_matches_known_dendrogram() {
+        let vectors = vec![vec![0.0], vec![1.0], vec![5.0], vec![7.0]];
+        let merges = agglomerative(&vectors, Linkage::Single).expect("agglomerative failed");
+
+        assert_eq!(
+            merges,
+            vec![
+                Merge {
+                    left: 0,
+                    right: 1,
+                    distance: 1.0,
+                    size: 2
+                },
+                Merge {
+                    left: 2,
+                    right: 3,
+                    distance: 2.0,
+                    size: 2
+                },
+                Merge {
+                    left: 4,
+                    right: 5,
+                    distance: 4.0,
+                    size: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_agglomerative_linkages_separate_blobs() {
+        for linkage in [
+            Linkage::Single,
+            Linkage::Complete,
+            Linkage::Average,
+            Linkage::Ward,
+        ] {
+            let merges = agglomerative(&blobs(), linkage).expect("agglomerative failed");
+
+            assert_eq!(merges.len(), 5);
+            assert_eq!(merges[4].size, 6);
+            assert!(merges.windows(2).all(|w| w[0].distance <= w[1].distance));
+            assert_eq!(cut_dendrogram(&merges, 6, 2), vec![0, 0, 0, 1, 1, 1]);
+        }
+    }
+
+    #[test]
+    fn test_cut_dendrogram_extremes() {
+        let merges = agglomerative(&blobs(), Linkage::Average).expect("agglomerative failed");
+
+        assert_eq!(cut_dendrogram(&merges, 6, 6), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(cut_dendrogram(&merges, 6, 1), vec![0; 6]);
+    }
+
+    #[test]
+    fn test_silhouette() {
+        let good = silhouette(&blobs(), &[0, 0, 0, 1, 1, 1], 100, 0);
+        let bad = silhouette(&blobs(), &[0, 1, 0, 1, 0, 1], 100, 0);
+
+        assert!(good > 0.8);
+        assert!(bad < 0.0);
+    }
+
+    #[test]
+    fn test_select_k() {
+        let (best, scores, result) = select_k(
+            &blobs(),
+            2,
+            4,
+            100,
+            0,
+            |k| kmeans(&blobs(), k, &params(KMeansMethod::Full)),
+            |result| &result.assignments,
+        )
+        .expect("select_k failed");
+
+        assert_eq!(best, 2);
+        assert_eq!(scores.len(), 3);
+        assert_eq!(result.centroids.len(), 2);
+
+        assert!(select_k(&blobs(), 1, 4, 100, 0, |_| Ok(vec![]), Vec::as_slice).is_err());
+        assert!(select_k(&blobs(), 2, 6, 100, 0, |_| Ok(vec![]), Vec::as_slice).is_err());
+    }
+
+    #[test]
+    fn test_silhouette_sample() {
+        let vectors: Vec<Vec<f32>> = (0..200)
+            .map(|i| vec![(i % 2) as f32 * 10.0, (i / 2) as f32 * 0.01])
+            .collect();
+        let assignments: Vec<usize> = (0..200).map(|i| i % 2).collect();
+
+        let exact = silhouette(&vectors, &assignments, 200, 0);
+        let sampled = silhouette(&vectors, &assignments, 20, 0);
+        assert!(exact > 0.9);
+        assert!((exact - sampled).abs() < 0.05);
+        assert_eq!(sampled, silhouette(&vectors, &assignments, 20, 0));
+    }
+
+    #[test]
+    fn test_representatives() {
+        let vectors = blobs();
+        let centroids = vec![vec![1.0, 0.0], vec![-1.0, 0.0]];
+        let assignments = vec![0, 0, 0, 1, 1, 1];
+
+        assert_eq!(
+            representatives(&vectors, &centroids, &assignments),
+            vec![Some(0), Some(3)]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::sent_transform::{self, SentenceTransformer};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+type CacheKey = [u8; 32];
+
+struct CacheEntry {
+    embedding: sbert::Embeddings,
+    last_used: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CacheStats {
+    pub model: String,
+    pub capacity: usize,
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub struct EmbeddingCache {
+    model_id: String,
+    capacity: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    recency: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl EmbeddingCache {
+    pub fn new(model_id: &str, capacity: usize) -> EmbeddingCache {
+        EmbeddingCache {
+            model_id: String::from(model_id),
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn key_for(&self, text: &str) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(self.model_id.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(text.as_bytes());
+
+        hasher.finalize().into()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub fn get(&mut self, text: &str) -> Option<sbert::Embeddings> {
+        let key = self.key_for(text);
+        let now = self.tick();
+
+        match self.entries.get_mut(&key) {
+            Some(entry) => {
+                self.recency.remove(&entry.last_used);
+                self.recency.insert(now, key);
+                entry.last_used = now;
+                self.hits += 1;
+
+                Some(entry.embedding.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, text: &str, embedding: sbert::Embeddings) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = self.key_for(text);
+        let now = self.tick();
+
+        if let Some(prev) = self.entries.insert(
+            key,
+            CacheEntry {
+                embedding,
+                last_used: now,
+            },
+        ) {
+            self.recency.remove(&prev.last_used);
+        }
+        self.recency.insert(now, key);
+
+        while self.entries.len() > self.capacity {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn compute_normalized_embeddings(
+        &mut self,
+        model: &SentenceTransformer,
+        input: &[&str],
+    ) -> Result<Vec<sbert::Embeddings>, sbert::Error> {
+        let mut embeddings: Vec<Option<sbert::Embeddings>> =
+            input.iter().map(|text| self.get(text)).collect();
+
+        let missing: Vec<usize> = embeddings
+            .iter()
+            .enumerate()
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(idx, _)| idx)
+            .collect();
+
+        if !missing.is_empty() {
+            let missing_strs: Vec<&str> = missing.iter().map(|idx| input[*idx]).collect();
+            let computed = sent_transform::compute_normalized_embeddings(model, &missing_strs)?;
+
+            for (idx, embedding) in missing.into_iter().zip(computed) {
+                self.insert(input[idx], embedding.clone());
+                embeddings[idx] = Some(embedding);
+            }
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    pub fn compute_normalized_embedding(
+        &mut self,
+        model: &SentenceTransformer,
+        input: &str,
+    ) -> Result<sbert::Embeddings, sbert::Error> {
+        self.compute_normalized_embeddings(model, &[input])
+            .map(|e| e.first().unwrap().clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            model: self.model_id.clone(),
+            capacity: self.capacity,
+            size: self.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_counts_hits_and_misses() {
+        let mut cache = EmbeddingCache::new("model", 2);
+
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a", vec![1.0, 0.0]);
+        assert_eq!(cache.get("a"), Some(vec![1.0, 0.0]));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.size), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::new("model", 2);
+
+        cache.insert("a", vec![1.0]);
+        cache.insert("b", vec![2.0]);
+        cache.get("a");
+        cache.insert("c", vec![3.0]);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        assert_eq!(cache.get("c"), Some(vec![3.0]));
+    }
+
+    #[test]
+    fn test_keys_include_model_id() {
+        let cache_a = EmbeddingCache::new("model-a", 1);
+        let cache_b = EmbeddingCache::new("model-b", 1);
+
+        assert_ne!(cache_a.key_for("text"), cache_b.key_for("text"));
+    }
+
+    #[test]
+    fn test_zero_capacity_never_stores() {
+        let mut cache = EmbeddingCache::new("model", 0);
+        cache.insert("a", vec![1.0]);
+
+        assert!(cache.is_empty());
+    }
+}
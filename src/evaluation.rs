@@ -0,0 +1,365 @@
+use crate::vector_index::{search_collapsed, GuardedIndex, SearchResult};
+use sbert::Embeddings;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// `Pca` evaluates a reduction to `dims` dimensions, fitted on `sample`
+// embeddings, before it is applied to the index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApproximateMethod {
+    Binary { oversample: f32 },
+    Pca { dims: usize, sample: usize },
+}
+
+impl ApproximateMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApproximateMethod::Binary { .. } => "binary",
+            ApproximateMethod::Pca { .. } => "pca",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Latency {
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl Latency {
+    pub fn from_samples(samples: &[f64]) -> Latency {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|x, y| x.total_cmp(y));
+
+        Latency {
+            mean: sorted.iter().sum::<f64>() / sorted.len().max(1) as f64,
+            p50: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RecallReport {
+    pub method: &'static str,
+    pub k: usize,
+    pub queries: usize,
+    pub recall: f32,
+    pub min_recall: f32,
+    pub exact_latency_ms: Latency,
+    pub approximate_latency_ms: Latency,
+}
+
+// The fraction of `exact` found in `approximate`.
+pub fn recall<T: PartialEq>(exact: &[T], approximate: &[T]) -> f32 {
+    if exact.is_empty() {
+        return 1.0;
+    }
+
+    let found = exact
+        .iter()
+        .filter(|item| approximate.contains(item))
+        .count();
+
+    found as f32 / exact.len() as f32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RankingMethod {
+    Cosine,
+    ExemplarSVM,
+    Binary { oversample: f32 },
+}
+
+impl RankingMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RankingMethod::Cosine => "cosine",
+            RankingMethod::ExemplarSVM => "svm",
+            RankingMethod::Binary { .. } => "binary",
+        }
+    }
+}
+
+// A relevant entry of a labeled query, either an id with a grade of 1 or an
+// id with its own grade.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Judgement {
+    Relevant(String),
+    Graded { id: String, grade: f32 },
+}
+
+impl Judgement {
+    pub fn id(&self) -> &str {
+        match self {
+            Judgement::Relevant(id) | Judgement::Graded { id, .. } => id,
+        }
+    }
+
+    pub fn grade(&self) -> f32 {
+        match self {
+            Judgement::Relevant(_) => 1.0,
+            Judgement::Graded { grade, .. } => *grade,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MethodRelevance {
+    pub method: &'static str,
+    pub ndcg: f32,
+    pub mrr: f32,
+    pub recall: f32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RelevanceReport {
+    pub k: usize,
+    pub queries: usize,
+    pub methods: Vec<MethodRelevance>,
+}
+
+// Discounted cumulative gain, with linear gains and a log2 discount.
+fn dcg(gains: impl Iterator<Item = f32>) -> f32 {
+    gains
+        .enumerate()
+        .map(|(rank, gain)| gain / (rank as f32 + 2.0).log2())
+        .sum()
+}
+
+pub fn ndcg(ranked: &[&str], grades: &HashMap<&str, f32>, k: usize) -> f32 {
+    let mut ideal: Vec<f32> = grades.values().copied().collect();
+    ideal.sort_by(|x, y| y.total_cmp(x));
+
+    let ideal = dcg(ideal.into_iter().take(k));
+    if ideal <= 0.0 {
+        return 0.0;
+    }
+
+    dcg(ranked
+        .iter()
+        .take(k)
+        .map(|key| grades.get(key).copied().unwrap_or(0.0)))
+        / ideal
+}
+
+pub fn reciprocal_rank(ranked: &[&str], grades: &HashMap<&str, f32>, k: usize) -> f32 {
+    ranked
+        .iter()
+        .take(k)
+        .position(|key| matches!(grades.get(key), Some(grade) if *grade > 0.0))
+        .map_or(0.0, |rank| 1.0 / (rank as f32 + 1.0))
+}
+
+// The key each result is judged by: its own id if it was judged, else its
+// parent, so judgements may name chunks or whole documents. Only the first
+// result of each key is kept, so chunks of a relevant parent count once.
+fn ranked_keys<'a>(results: &'a [SearchResult], grades: &HashMap<&str, f32>) -> Vec<&'a str> {
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    results
+        .iter()
+        .map(|result| match result.parent.as_deref() {
+            Some(parent) if !grades.contains_key(result.id.as_str()) => parent,
+            _ => result.id.as_str(),
+        })
+        .filter(|key| seen.insert(key))
+        .collect()
+}
+
+// Ranks every query with every method and averages nDCG@k, MRR@k and
+// recall@k over the queries. With `collapse`, results are collapsed by
+// parent so that chunks of the same parent cannot crowd others out of the
+// top k.
+pub fn evaluate_relevance(
+    index: &GuardedIndex,
+    queries: &[Embeddings],
+    judgements: &[Vec<Judgement>],
+    methods: &[RankingMethod],
+    k: usize,
+    collapse: bool,
+) -> Result<RelevanceReport, String> {
+    let methods = methods
+        .iter()
+        .map(|method| {
+            let (mut ndcg_sum, mut mrr_sum, mut recall_sum) = (0.0, 0.0, 0.0);
+
+            for (query, judgements) in queries.iter().zip(judgements) {
+                let search = |nsearch: usize| match method {
+                    RankingMethod::Cosine => index.search_knn(query, nsearch),
+                    RankingMethod::ExemplarSVM => index.search_exemplar_svm(query, nsearch),
+                    RankingMethod::Binary { oversample } => {
+                        index.search_quantized(query, nsearch, *oversample)
+                    }
+                };
+                let results = if collapse {
+                    search_collapsed(k, index.len(), search)
+                } else {
+                    search(k)
+                }?;
+
+                let grades: HashMap<&str, f32> = judgements
+                    .iter()
+                    .filter(|judgement| judgement.grade() > 0.0)
+                    .map(|judgement| (judgement.id(), judgement.grade()))
+                    .collect();
+                let relevant: Vec<&str> = grades.keys().copied().collect();
+                let ranked = ranked_keys(&results, &grades);
+                let top_k = &ranked[..ranked.len().min(k)];
+
+                ndcg_sum += ndcg(&ranked, &grades, k);
+                mrr_sum += reciprocal_rank(&ranked, &grades, k);
+                recall_sum += recall(&relevant, top_k);
+            }
+
+            let count = queries.len().max(1) as f32;
+            Ok(MethodRelevance {
+                method: method.name(),
+                ndcg: ndcg_sum / count,
+                mrr: mrr_sum / count,
+                recall: recall_sum / count,
+            })
+        })
+        .collect::<Result<Vec<MethodRelevance>, String>>()?;
+
+    Ok(RelevanceReport {
+        k,
+        queries: queries.len(),
+        methods,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::TextBody;
+
+    #[test]
+    fn test_recall() {
+        assert_eq!(recall(&[1, 2, 3, 4], &[4, 2, 9, 8]), 0.5);
+        assert_eq!(recall(&[], &[1]), 1.0);
+    }
+
+    fn grades<'a>(grades: &[(&'a str, f32)]) -> HashMap<&'a str, f32> {
+        grades.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_ndcg_and_reciprocal_rank() {
+        let relevant = grades(&[("a", 2.0), ("b", 1.0)]);
+
+        assert!((ndcg(&["a", "b", "c"], &relevant, 3) - 1.0).abs() < 1e-6);
+        let swapped = (1.0 + 2.0 / 3.0_f32.log2()) / (2.0 + 1.0 / 3.0_f32.log2());
+        assert!((ndcg(&["b", "a"], &relevant, 3) - swapped).abs() < 1e-6);
+        assert_eq!(ndcg(&["c", "d"], &relevant, 2), 0.0);
+        assert_eq!(ndcg(&["a"], &grades(&[]), 2), 0.0);
+
+        assert_eq!(reciprocal_rank(&["c", "b", "a"], &relevant, 3), 0.5);
+        assert_eq!(reciprocal_rank(&["c", "d", "a"], &relevant, 2), 0.0);
+    }
+
+    #[test]
+    fn test_ranked_keys() {
+        let result = |id: &str, parent: Option<&str>| SearchResult {
+            id: String::from(id),
+            text: String::new(),
+            score: 0.0,
+            parent: parent.map(String::from),
+        };
+
+        let results = [
+            result("doc#1", Some("doc")),
+            result("other", None),
+            result("doc#0", Some("doc")),
+            result("page#2", Some("page")),
+        ];
+
+        assert_eq!(
+            ranked_keys(&results, &grades(&[("doc", 1.0)])),
+            vec!["doc", "other", "page"]
+        );
+        assert_eq!(
+            ranked_keys(&results, &grades(&[("doc#0", 1.0)])),
+            vec!["doc", "other", "doc#0", "page"]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_relevance() {
+        let texts = ["a", "b", "c"]
+            .iter()
+            .map(|id| TextBody {
+                id: id.to_string(),
+                text: id.to_string(),
+                parent: None,
+            })
+            .collect();
+        let index = GuardedIndex::new(texts, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.6, 0.8]])
+            .expect("Could not create index");
+
+        let report = evaluate_relevance(
+            &index,
+            &[vec![1.0, 0.0], vec![0.0, 1.0]],
+            &[
+                vec![Judgement::Relevant(String::from("a"))],
+                vec![Judgement::Graded {
+                    id: String::from("a"),
+                    grade: 1.0,
+                }],
+            ],
+            &[RankingMethod::Cosine],
+            1,
+            false,
+        )
+        .expect("Could not evaluate");
+
+        assert_eq!(report.queries, 2);
+        assert_eq!(
+            report.methods,
+            vec![MethodRelevance {
+                method: "cosine",
+                ndcg: 0.5,
+                mrr: 0.5,
+                recall: 0.5,
+            }]
+        );
+        assert!(evaluate_relevance(
+            &index,
+            &[vec![1.0, 0.0]],
+            &[vec![Judgement::Relevant(String::from("a"))]],
+            &[RankingMethod::Binary { oversample: 4.0 }],
+            1,
+            false,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_latency() {
+        let samples: Vec<f64> = (1..=100).rev().map(|i| i as f64).collect();
+        let latency = Latency::from_samples(&samples);
+
+        assert_eq!(latency.mean, 50.5);
+        assert_eq!(latency.p50, 50.0);
+        assert_eq!(latency.p90, 90.0);
+        assert_eq!(latency.p99, 99.0);
+        assert_eq!(latency.max, 100.0);
+        assert_eq!(Latency::from_samples(&[]).p99, 0.0);
+    }
+}
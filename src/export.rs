@@ -0,0 +1,307 @@
+use crate::vector_index::TextBody;
+use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Jsonl,
+    Npy,
+    Ids,
+    Arrow,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<ExportFormat, String> {
+        match format {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "npy" => Ok(ExportFormat::Npy),
+            "ids" => Ok(ExportFormat::Ids),
+            "arrow" => Ok(ExportFormat::Arrow),
+            _ => Err(format!(
+                "Invalid format '{format}'. Must be 'jsonl', 'npy', 'ids' or 'arrow'"
+            )),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Npy => "application/octet-stream",
+            ExportFormat::Ids => "text/plain; charset=utf-8",
+            ExportFormat::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Npy => "npy",
+            ExportFormat::Ids => "ids.txt",
+            ExportFormat::Arrow => "arrows",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    id: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<&'a str>,
+    embedding: &'a [f32],
+}
+
+// Version 1.0 of the .npy format: a magic string, a little-endian header
+// length, and a Python dict literal padded so the data starts on a 64 byte
+// boundary.
+pub fn npy_header(rows: usize, dim: usize) -> Vec<u8> {
+    let dict = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {dim}), }}");
+    let unpadded = 6 + 2 + 2 + dict.len() + 1;
+    let padding = (64 - unpadded % 64) % 64;
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&((dict.len() + padding + 1) as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(header.len() + padding, b' ');
+    header.push(b'\n');
+
+    header
+}
+
+fn arrow_schema(dim: usize) -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("text", DataType::Utf8, false),
+        Field::new("parent", DataType::Utf8, true),
+        Field::new(
+            "embedding",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, false)),
+                dim as i32,
+            ),
+            false,
+        ),
+    ])
+}
+
+fn arrow_batch(
+    schema: &Schema,
+    dim: usize,
+    texts: &[TextBody],
+    embeddings: &[Vec<f32>],
+) -> Result<RecordBatch, ArrowError> {
+    let values = Float32Array::from(embeddings.concat());
+    let embedding = FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Float32, false)),
+        dim as i32,
+        Arc::new(values),
+        None,
+    )?;
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(texts.iter().map(|t| &t.id))),
+        Arc::new(StringArray::from_iter_values(texts.iter().map(|t| &t.text))),
+        Arc::new(StringArray::from_iter(
+            texts.iter().map(|t| t.parent.as_deref()),
+        )),
+        Arc::new(embedding),
+    ];
+
+    RecordBatch::try_new(Arc::new(schema.clone()), columns)
+}
+
+// Encodes an index one slice at a time, so exports can be streamed without
+// holding the whole encoded index in memory. The header, every chunk and the
+// footer are written to the response in order.
+pub enum Exporter {
+    Jsonl,
+    Npy {
+        rows: usize,
+        dim: usize,
+    },
+    Ids,
+    Arrow {
+        dim: usize,
+        schema: Schema,
+        writer: Option<StreamWriter<Vec<u8>>>,
+    },
+}
+
+impl Exporter {
+    pub fn new(format: ExportFormat, rows: usize, dim: usize) -> Exporter {
+        match format {
+            ExportFormat::Jsonl => Exporter::Jsonl,
+            ExportFormat::Npy => Exporter::Npy { rows, dim },
+            ExportFormat::Ids => Exporter::Ids,
+            ExportFormat::Arrow => Exporter::Arrow {
+                dim,
+                schema: arrow_schema(dim),
+                writer: None,
+            },
+        }
+    }
+
+    pub fn header(&mut self) -> Result<Vec<u8>, String> {
+        match self {
+            Exporter::Npy { rows, dim } => Ok(npy_header(*rows, *dim)),
+            Exporter::Arrow { schema, writer, .. } => {
+                let mut stream = StreamWriter::try_new(vec![], schema)
+                    .map_err(|err| format!("Could not write Arrow schema: {err}"))?;
+                let header = std::mem::take(stream.get_mut());
+                *writer = Some(stream);
+
+                Ok(header)
+            }
+            Exporter::Jsonl | Exporter::Ids => Ok(vec![]),
+        }
+    }
+
+    pub fn chunk(
+        &mut self,
+        texts: &[TextBody],
+        embeddings: &[Vec<f32>],
+    ) -> Result<Vec<u8>, String> {
+        let mut bytes: Vec<u8> = vec![];
+
+        match self {
+            Exporter::Jsonl => {
+                for (text_body, embedding) in texts.iter().zip(embeddings) {
+                    let record = JsonlRecord {
+                        id: &text_body.id,
+                        text: &text_body.text,
+                        parent: text_body.parent.as_deref(),
+                        embedding,
+                    };
+                    serde_json::to_writer(&mut bytes, &record)
+                        .map_err(|err| format!("Could not encode {}: {err}", text_body.id))?;
+                    bytes.push(b'\n');
+                }
+            }
+            Exporter::Npy { .. } => {
+                for embedding in embeddings {
+                    for x in embedding {
+                        bytes.extend_from_slice(&x.to_le_bytes());
+                    }
+                }
+            }
+            Exporter::Ids => {
+                for text_body in texts {
+                    bytes.extend_from_slice(text_body.id.as_bytes());
+                    bytes.push(b'\n');
+                }
+            }
+            Exporter::Arrow {
+                dim,
+                schema,
+                writer,
+            } => {
+                let stream = writer
+                    .as_mut()
+                    .ok_or_else(|| String::from("Arrow header was not written"))?;
+                arrow_batch(schema, *dim, texts, embeddings)
+                    .and_then(|batch| stream.write(&batch))
+                    .map_err(|err| format!("Could not write Arrow batch: {err}"))?;
+                bytes = std::mem::take(stream.get_mut());
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn footer(&mut self) -> Result<Vec<u8>, String> {
+        match self {
+            Exporter::Arrow { writer, .. } => match writer.as_mut() {
+                Some(stream) => {
+                    stream
+                        .finish()
+                        .map_err(|err| format!("Could not finish Arrow stream: {err}"))?;
+                    Ok(std::mem::take(stream.get_mut()))
+                }
+                None => Err(String::from("Arrow header was not written")),
+            },
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_ipc::reader::StreamReader;
+
+    fn text_bodies() -> Vec<TextBody> {
+        vec![
+            TextBody {
+                id: String::from("a"),
+                text: String::from("first"),
+                parent: None,
+            },
+            TextBody {
+                id: String::from("b#0"),
+                text: String::from("second"),
+                parent: Some(String::from("b")),
+            },
+        ]
+    }
+
+    fn export(format: ExportFormat) -> Vec<u8> {
+        let texts = text_bodies();
+        let embeddings = [vec![1.0, 0.0], vec![0.5, -0.5]];
+        let mut exporter = Exporter::new(format, 2, 2);
+
+        let mut bytes = exporter.header().unwrap();
+        bytes.extend(exporter.chunk(&texts[..1], &embeddings[..1]).unwrap());
+        bytes.extend(exporter.chunk(&texts[1..], &embeddings[1..]).unwrap());
+        bytes.extend(exporter.footer().unwrap());
+
+        bytes
+    }
+
+    #[test]
+    fn test_npy() {
+        let header = npy_header(2, 2);
+        assert_eq!(header.len() % 64, 0);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(
+            u16::from_le_bytes([header[8], header[9]]) as usize,
+            header.len() - 10
+        );
+
+        let bytes = export(ExportFormat::Npy);
+        assert_eq!(bytes.len(), header.len() + 4 * 4);
+        assert_eq!(&bytes[header.len() + 12..], &(-0.5_f32).to_le_bytes());
+    }
+
+    #[test]
+    fn test_jsonl_and_ids() {
+        assert_eq!(
+            String::from_utf8(export(ExportFormat::Jsonl)).unwrap(),
+            "{\"id\":\"a\",\"text\":\"first\",\"embedding\":[1.0,0.0]}\n\
+             {\"id\":\"b#0\",\"text\":\"second\",\"parent\":\"b\",\"embedding\":[0.5,-0.5]}\n"
+        );
+        assert_eq!(
+            String::from_utf8(export(ExportFormat::Ids)).unwrap(),
+            "a\nb#0\n"
+        );
+    }
+
+    #[test]
+    fn test_arrow_roundtrip() {
+        let bytes = export(ExportFormat::Arrow);
+        let reader = StreamReader::try_new(bytes.as_slice(), None).expect("Invalid stream");
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].num_rows(), 1);
+
+        let ids = batches[1]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(ids.value(0), "b#0");
+    }
+}
@@ -0,0 +1,419 @@
+use crate::sent_transform::l2_normalize;
+use crate::vector_index::TextBody;
+use arrow_array::{Array, FixedSizeListArray, Float32Array, ListArray, RecordBatch, StringArray};
+use arrow_ipc::reader::StreamReader;
+use arrow_schema::DataType;
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    Jsonl,
+    Npy,
+    Arrow,
+}
+
+impl ImportFormat {
+    pub fn parse(format: &str) -> Result<ImportFormat, String> {
+        match format {
+            "jsonl" => Ok(ImportFormat::Jsonl),
+            "npy" => Ok(ImportFormat::Npy),
+            "arrow" => Ok(ImportFormat::Arrow),
+            _ => Err(format!(
+                "Invalid format '{format}'. Must be 'jsonl', 'npy' or 'arrow'"
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonlRecord {
+    id: String,
+    text: String,
+    #[serde(default)]
+    parent: Option<String>,
+    embedding: Vec<f32>,
+}
+
+fn lines(bytes: &[u8]) -> Result<Vec<(usize, &str)>, String> {
+    let text = std::str::from_utf8(bytes).map_err(|err| format!("Invalid UTF-8: {err}"))?;
+
+    Ok(text
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect())
+}
+
+// One `TextBody` with an `embedding` attribute per line, as written by the
+// jsonl export.
+pub fn parse_jsonl(bytes: &[u8]) -> Result<(Vec<TextBody>, Vec<Vec<f32>>), String> {
+    let mut texts: Vec<TextBody> = vec![];
+    let mut embeddings: Vec<Vec<f32>> = vec![];
+
+    for (line, raw) in lines(bytes)? {
+        let record = serde_json::from_str::<JsonlRecord>(raw)
+            .map_err(|err| format!("Invalid record on line {line}: {err}"))?;
+
+        texts.push(TextBody {
+            id: record.id,
+            text: record.text,
+            parent: record.parent,
+        });
+        embeddings.push(record.embedding);
+    }
+
+    Ok((texts, embeddings))
+}
+
+// Entries for a sidecar file of ids, one per line, in the same order as the
+// rows of an npy matrix. The entries have no text.
+pub fn parse_ids(bytes: &[u8]) -> Result<Vec<TextBody>, String> {
+    Ok(lines(bytes)?
+        .into_iter()
+        .map(|(_, id)| TextBody {
+            id: String::from(id),
+            text: String::new(),
+            parent: None,
+        })
+        .collect())
+}
+
+// Entries for a sidecar file of `TextBody` JSON objects, one per line, in the
+// same order as the rows of an npy matrix.
+pub fn parse_texts(bytes: &[u8]) -> Result<Vec<TextBody>, String> {
+    lines(bytes)?
+        .into_iter()
+        .map(|(line, raw)| {
+            serde_json::from_str::<TextBody>(raw)
+                .map_err(|err| format!("Invalid TextBody on line {line}: {err}"))
+        })
+        .collect()
+}
+
+pub struct NpyHeader {
+    pub descr: String,
+    pub rows: usize,
+    pub cols: usize,
+    pub data_start: usize,
+}
+
+impl NpyHeader {
+    // The number of bytes of data for elements of `width` bytes. The shape
+    // comes from the file, so an overflow is an error rather than a panic.
+    pub fn data_len(&self, width: usize) -> Result<usize, String> {
+        self.rows
+            .checked_mul(self.cols)
+            .and_then(|elements| elements.checked_mul(width))
+            .ok_or_else(|| format!("npy shape ({}, {}) is too large", self.rows, self.cols))
+    }
+}
+
+// Parses the header of any version of the .npy format, which must describe a
+// 2-dimensional, C-ordered matrix.
+pub fn parse_npy_header(bytes: &[u8]) -> Result<NpyHeader, String> {
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(String::from("Not an npy file"));
+    }
+
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            12,
+        ),
+        version => return Err(format!("Unsupported npy version {version}")),
+    };
+    let data_start = header_start + header_len;
+    if bytes.len() < data_start {
+        return Err(String::from("Truncated npy header"));
+    }
+    let header = String::from_utf8_lossy(&bytes[header_start..data_start]);
+
+    let descr = Regex::new(r"'descr':\s*'([^']*)'")
+        .unwrap()
+        .captures(&header)
+        .map(|captures| captures[1].to_string())
+        .ok_or_else(|| String::from("npy header has no descr"))?;
+    if header.contains("'fortran_order': True") {
+        return Err(String::from("Fortran ordered npy files are not supported"));
+    }
+    let (rows, cols) = Regex::new(r"'shape':\s*\(\s*(\d+)\s*,\s*(\d+)\s*,?\s*\)")
+        .unwrap()
+        .captures(&header)
+        .map(|captures| (captures[1].parse::<usize>(), captures[2].parse::<usize>()))
+        .and_then(|(rows, cols)| rows.ok().zip(cols.ok()))
+        .ok_or_else(|| String::from("npy files must contain a 2-dimensional matrix"))?;
+
+    Ok(NpyHeader {
+        descr,
+        rows,
+        cols,
+        data_start,
+    })
+}
+
+// Reads a float32 or float64 matrix from an .npy file.
+pub fn parse_npy(bytes: &[u8]) -> Result<Vec<Vec<f32>>, String> {
+    let header = parse_npy_header(bytes)?;
+    let (rows, cols) = (header.rows, header.cols);
+
+    let data = &bytes[header.data_start..];
+    let width = match header.descr.as_str() {
+        "<f4" => 4,
+        "<f8" => 8,
+        descr => {
+            return Err(format!(
+                "Unsupported npy dtype '{descr}'. Must be '<f4' or '<f8'"
+            ))
+        }
+    };
+    let expected = header.data_len(width)?;
+    if data.len() != expected {
+        return Err(format!(
+            "npy data has {} bytes, expected {expected} for shape ({rows}, {cols})",
+            data.len(),
+        ));
+    }
+
+    let values: Vec<f32> = if width == 4 {
+        data.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    } else {
+        data.chunks_exact(8)
+            .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32)
+            .collect()
+    };
+
+    Ok(values
+        .chunks(cols.max(1))
+        .take(rows)
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+fn string_column(batch: &RecordBatch, name: &str) -> Result<Option<StringArray>, String> {
+    batch
+        .column_by_name(name)
+        .map(|column| {
+            column
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .cloned()
+                .ok_or_else(|| format!("Arrow column '{name}' must be a string column"))
+        })
+        .transpose()
+}
+
+fn embedding_values(list: &dyn Array, row: usize) -> Result<Vec<f32>, String> {
+    let value = match list.data_type() {
+        DataType::FixedSizeList(_, _) => list
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .map(|list| list.value(row)),
+        DataType::List(_) => list
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .map(|list| list.value(row)),
+        _ => None,
+    };
+
+    value
+        .as_ref()
+        .and_then(|value| value.as_any().downcast_ref::<Float32Array>())
+        .map(|value| value.values().to_vec())
+        .ok_or_else(|| String::from("Arrow column 'embedding' must be a list of float32"))
+}
+
+// Reads an Arrow IPC stream with `id`, `text`, optional `parent` and
+// `embedding` columns, as written by the arrow export. Embeddings may be
+// fixed size or variable size lists of float32.
+pub fn parse_arrow(bytes: &[u8]) -> Result<(Vec<TextBody>, Vec<Vec<f32>>), String> {
+    let reader =
+        StreamReader::try_new(bytes, None).map_err(|err| format!("Invalid Arrow stream: {err}"))?;
+    for column in ["id", "embedding"] {
+        if reader.schema().column_with_name(column).is_none() {
+            return Err(format!("Arrow stream has no '{column}' column"));
+        }
+    }
+
+    let mut texts: Vec<TextBody> = vec![];
+    let mut embeddings: Vec<Vec<f32>> = vec![];
+
+    for batch in reader {
+        let batch = batch.map_err(|err| format!("Invalid Arrow batch: {err}"))?;
+        let ids = string_column(&batch, "id")?.unwrap();
+        let text_column = string_column(&batch, "text")?;
+        let parents = string_column(&batch, "parent")?;
+        let embedding_column = batch.column_by_name("embedding").unwrap();
+
+        for row in 0..batch.num_rows() {
+            if ids.is_null(row) || embedding_column.is_null(row) {
+                return Err(format!(
+                    "Row {} has a null id or embedding",
+                    texts.len() + 1
+                ));
+            }
+
+            texts.push(TextBody {
+                id: String::from(ids.value(row)),
+                text: text_column
+                    .as_ref()
+                    .filter(|column| !column.is_null(row))
+                    .map_or(String::new(), |column| String::from(column.value(row))),
+                parent: parents
+                    .as_ref()
+                    .filter(|column| !column.is_null(row))
+                    .map(|column| String::from(column.value(row))),
+            });
+            embeddings.push(embedding_values(embedding_column.as_ref(), row)?);
+        }
+    }
+
+    Ok((texts, embeddings))
+}
+
+// Imported embeddings may come from anywhere, so every embedding must have the
+// model's dimension and a finite, non-zero norm. They are then l2-normalized
+// like the embeddings computed by the model.
+pub fn normalize_embeddings(
+    texts: &[TextBody],
+    embeddings: Vec<Vec<f32>>,
+    dim: usize,
+) -> Result<Vec<Vec<f32>>, String> {
+    if texts.len() != embeddings.len() {
+        return Err(format!(
+            "Got {} entries but {} embeddings",
+            texts.len(),
+            embeddings.len()
+        ));
+    }
+
+    texts
+        .iter()
+        .zip(embeddings)
+        .map(|(text_body, embedding)| {
+            if embedding.len() != dim {
+                return Err(format!(
+                    "Embedding of {} has dimension {} (expected {dim})",
+                    text_body.id,
+                    embedding.len()
+                ));
+            }
+
+            let norm: f32 = embedding.iter().map(|x| x * x).sum();
+            if !norm.is_finite() || norm == 0.0 {
+                return Err(format!(
+                    "Embedding of {} must be finite and non-zero",
+                    text_body.id
+                ));
+            }
+
+            Ok(l2_normalize(embedding))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ExportFormat, Exporter};
+
+    fn text_bodies() -> Vec<TextBody> {
+        vec![
+            TextBody {
+                id: String::from("a"),
+                text: String::from("first"),
+                parent: None,
+            },
+            TextBody {
+                id: String::from("b#0"),
+                text: String::from("second"),
+                parent: Some(String::from("b")),
+            },
+        ]
+    }
+
+    fn export(format: ExportFormat) -> Vec<u8> {
+        let embeddings = vec![vec![1.0, 0.0], vec![0.6, -0.8]];
+        let mut exporter = Exporter::new(format, 2, 2);
+
+        let mut bytes = exporter.header().unwrap();
+        bytes.extend(exporter.chunk(&text_bodies(), &embeddings).unwrap());
+        bytes.extend(exporter.footer().unwrap());
+
+        bytes
+    }
+
+    #[test]
+    fn test_roundtrip_exports() {
+        let (texts, embeddings) = parse_jsonl(&export(ExportFormat::Jsonl)).unwrap();
+        assert_eq!(texts[1].parent.as_deref(), Some("b"));
+        assert_eq!(embeddings[1], vec![0.6, -0.8]);
+
+        let (texts, embeddings) = parse_arrow(&export(ExportFormat::Arrow)).unwrap();
+        assert_eq!(texts[1].text, "second");
+        assert_eq!(embeddings[1], vec![0.6, -0.8]);
+
+        let embeddings = parse_npy(&export(ExportFormat::Npy)).unwrap();
+        let texts = parse_ids(&export(ExportFormat::Ids)).unwrap();
+        assert_eq!(texts[1].id, "b#0");
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.6, -0.8]]);
+    }
+
+    #[test]
+    fn test_parse_npy_float64() {
+        let dict = "{'descr': '<f8', 'fortran_order': False, 'shape': (1, 2), }";
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(dict.as_bytes());
+        bytes.extend_from_slice(&0.5_f64.to_le_bytes());
+        bytes.extend_from_slice(&(-2.0_f64).to_le_bytes());
+
+        assert_eq!(parse_npy(&bytes).unwrap(), vec![vec![0.5, -2.0]]);
+        assert!(parse_npy(&bytes[..bytes.len() - 1]).is_err());
+        assert!(parse_npy(b"not numpy").is_err());
+    }
+
+    #[test]
+    fn test_parse_npy_overflowing_shape() {
+        let dict = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, 2), }}",
+            usize::MAX / 2
+        );
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(dict.as_bytes());
+
+        assert!(parse_npy(&bytes).unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_jsonl(b"\n{\"id\": \"a\"}")
+            .unwrap_err()
+            .starts_with("Invalid record on line 2"));
+        assert!(parse_texts(b"{\"id\": \"a\", \"text\": \"first\"}\n").is_ok());
+    }
+
+    #[test]
+    fn test_normalize_embeddings() {
+        let texts = text_bodies();
+
+        let embeddings = normalize_embeddings(&texts, vec![vec![3.0, 4.0], vec![0.0, 2.0]], 2);
+        assert_eq!(embeddings.unwrap(), vec![vec![0.6, 0.8], vec![0.0, 1.0]]);
+
+        assert_eq!(
+            normalize_embeddings(&texts, vec![vec![3.0, 4.0], vec![1.0]], 2).unwrap_err(),
+            "Embedding of b#0 has dimension 1 (expected 2)"
+        );
+        assert_eq!(
+            normalize_embeddings(&texts, vec![vec![3.0, 4.0], vec![0.0, 2.0]], 3).unwrap_err(),
+            "Embedding of a has dimension 2 (expected 3)"
+        );
+        assert!(normalize_embeddings(&texts, vec![vec![3.0, 4.0], vec![0.0, 0.0]], 2).is_err());
+        assert!(normalize_embeddings(&texts, vec![vec![3.0, 4.0]], 2).is_err());
+    }
+}
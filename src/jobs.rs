@@ -0,0 +1,300 @@
+use crate::vector_index::Deduplicated;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Cancelling,
+    Cancelled,
+    Completed,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Cancelled | JobStatus::Completed | JobStatus::Failed
+        )
+    }
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct JobError {
+    pub item: usize,
+    pub error: String,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct JobReport {
+    pub id: u64,
+    pub index: String,
+    pub status: JobStatus,
+    pub total: usize,
+    pub embedded: usize,
+    pub errors: Vec<JobError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deduplicated: Vec<Deduplicated>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+}
+
+pub struct Job {
+    cancelled: AtomicBool,
+    report: Mutex<JobReport>,
+}
+
+impl Job {
+    fn new(id: u64, index: &str, total: usize) -> Job {
+        Job {
+            cancelled: AtomicBool::new(false),
+            report: Mutex::new(JobReport {
+                id,
+                index: String::from(index),
+                status: JobStatus::Queued,
+                total,
+                embedded: 0,
+                errors: vec![],
+                deduplicated: vec![],
+                size: None,
+            }),
+        }
+    }
+
+    pub fn report(&self) -> JobReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn start(&self) {
+        let mut report = self.report.lock().unwrap();
+        if report.status == JobStatus::Queued {
+            report.status = JobStatus::Running;
+        }
+    }
+
+    pub fn cancel(&self) {
+        let mut report = self.report.lock().unwrap();
+        if matches!(report.status, JobStatus::Queued | JobStatus::Running) {
+            self.cancelled.store(true, Ordering::SeqCst);
+            report.status = JobStatus::Cancelling;
+        }
+    }
+
+    pub fn record_progress(
+        &self,
+        embedded: usize,
+        errors: Vec<JobError>,
+        deduplicated: Vec<Deduplicated>,
+    ) {
+        let mut report = self.report.lock().unwrap();
+        report.embedded += embedded;
+        report.errors.extend(errors);
+        report.deduplicated.extend(deduplicated);
+    }
+
+    pub fn finish(&self, status: JobStatus, size: usize) {
+        let mut report = self.report.lock().unwrap();
+        report.status = status;
+        report.size = Some(size);
+    }
+
+    pub fn fail(&self, item: usize, error: String, size: usize) {
+        let mut report = self.report.lock().unwrap();
+        report.errors.push(JobError { item, error });
+        report.status = JobStatus::Failed;
+        report.size = Some(size);
+    }
+}
+
+const MAX_FINISHED_JOBS: usize = 100;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+// Jobs all contend for the model, so they run one at a time on a single
+// worker thread, in the order they were submitted. The worker stops once the
+// registry is dropped.
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: RwLock<BTreeMap<u64, Arc<Job>>>,
+    queue: Mutex<Sender<(Arc<Job>, Task)>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> JobRegistry {
+        let (sender, receiver) = mpsc::channel::<(Arc<Job>, Task)>();
+        std::thread::spawn(move || {
+            for (job, task) in receiver {
+                job.start();
+                task();
+            }
+        });
+
+        JobRegistry {
+            next_id: AtomicU64::new(0),
+            jobs: RwLock::new(BTreeMap::new()),
+            queue: Mutex::new(sender),
+        }
+    }
+
+    pub fn run<F: FnOnce() + Send + 'static>(&self, job: &Arc<Job>, task: F) {
+        self.queue
+            .lock()
+            .unwrap()
+            .send((Arc::clone(job), Box::new(task)))
+            .expect("Job worker stopped");
+    }
+
+    pub fn submit(&self, index: &str, total: usize) -> Arc<Job> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = Arc::new(Job::new(id, index, total));
+
+        let mut jobs = self.jobs.write().unwrap();
+        jobs.insert(id, Arc::clone(&job));
+        prune_finished(&mut jobs);
+
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn cancel_index(&self, index: &str) {
+        for job in self.jobs.read().unwrap().values() {
+            if job.report.lock().unwrap().index == index {
+                job.cancel();
+            }
+        }
+    }
+}
+
+impl Default for JobRegistry {
+    fn default() -> JobRegistry {
+        JobRegistry::new()
+    }
+}
+
+// Finished jobs are kept around so their results can be read, but only the
+// most recent MAX_FINISHED_JOBS of them.
+fn prune_finished(jobs: &mut BTreeMap<u64, Arc<Job>>) {
+    let finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| job.report().status.is_finished())
+        .map(|(id, _)| *id)
+        .collect();
+
+    if finished.len() > MAX_FINISHED_JOBS {
+        for id in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+            jobs.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let registry = JobRegistry::new();
+        let job = registry.submit("shakespeare", 3);
+
+        job.record_progress(
+            2,
+            vec![JobError {
+                item: 1,
+                error: String::from("bad"),
+            }],
+            vec![],
+        );
+        job.finish(JobStatus::Completed, 1);
+
+        let report = registry.get(1).expect("Job not found").report();
+        assert_eq!(report.status, JobStatus::Completed);
+        assert_eq!(
+            (report.total, report.embedded, report.size),
+            (3, 2, Some(1))
+        );
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_only_running_jobs() {
+        let registry = JobRegistry::new();
+        let running = registry.submit("a", 1);
+        let finished = registry.submit("b", 1);
+        finished.finish(JobStatus::Completed, 1);
+
+        running.cancel();
+        finished.cancel();
+
+        assert!(running.is_cancelled());
+        assert_eq!(running.report().status, JobStatus::Cancelling);
+        assert!(!finished.is_cancelled());
+        assert_eq!(finished.report().status, JobStatus::Completed);
+    }
+
+    #[test]
+    fn test_cancel_index_jobs() {
+        let registry = JobRegistry::new();
+        let deleted = registry.submit("deleted", 1);
+        let other = registry.submit("other", 1);
+
+        registry.cancel_index("deleted");
+
+        assert_eq!(deleted.report().status, JobStatus::Cancelling);
+        assert_eq!(other.report().status, JobStatus::Queued);
+    }
+
+    #[test]
+    fn test_jobs_run_in_order() {
+        let registry = JobRegistry::new();
+        let order = Arc::new(Mutex::new(vec![]));
+        let (done, finished) = mpsc::channel();
+
+        for n in 0..3 {
+            let job = registry.submit("a", 1);
+            assert_eq!(job.report().status, JobStatus::Queued);
+
+            let order = Arc::clone(&order);
+            let done = done.clone();
+            let running = Arc::clone(&job);
+            registry.run(&job, move || {
+                assert_eq!(running.report().status, JobStatus::Running);
+                order.lock().unwrap().push(n);
+                done.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..3 {
+            finished
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("Job did not run");
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_prunes_oldest_finished_jobs() {
+        let registry = JobRegistry::new();
+        let running = registry.submit("running", 1);
+        for _ in 0..MAX_FINISHED_JOBS + 1 {
+            registry.submit("done", 1).finish(JobStatus::Completed, 1);
+        }
+        registry.submit("trigger", 1);
+
+        assert!(registry.get(running.report().id).is_some());
+        assert!(registry.get(2).is_none());
+        assert!(registry.get(3).is_some());
+    }
+}
@@ -1,2 +1,20 @@
+pub mod anomaly;
+pub mod chunking;
+pub mod classifier;
+pub mod cluster;
+pub mod embedding_cache;
+pub mod evaluation;
+pub mod export;
+pub mod import;
+pub mod jobs;
+pub mod lsh;
+pub mod mapped;
+pub mod matrix;
+pub mod names;
+pub mod ndjson;
+pub mod preprocess;
+pub mod projection;
+pub mod quantize;
 pub mod sent_transform;
+pub mod snapshot;
 pub mod vector_index;
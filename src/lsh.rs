@@ -0,0 +1,288 @@
+use crate::matrix::dot;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+const BITS_PER_TABLE: usize = 16;
+const MAX_TABLES: usize = 64;
+const TARGET_RECALL: f64 = 0.99;
+const SEED: u64 = 0x5eed;
+
+// Box-Muller transform, which avoids pulling in rand_distr for a normal
+// distribution.
+fn gaussian(rng: &mut StdRng) -> f32 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+
+    ((-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()) as f32
+}
+
+// Two vectors at cosine similarity s fall on the same side of a random
+// hyperplane with probability 1 - acos(s) / pi. Enough tables are used for a
+// pair at exactly the threshold to share at least one bucket with
+// TARGET_RECALL probability, up to MAX_TABLES.
+fn tables_for_threshold(threshold: f32) -> usize {
+    let p_bit = 1.0 - (threshold.clamp(-1.0, 1.0) as f64).acos() / std::f64::consts::PI;
+    let p_table = p_bit.powi(BITS_PER_TABLE as i32);
+
+    if p_table >= TARGET_RECALL {
+        return 1;
+    }
+
+    let tables = ((1.0 - TARGET_RECALL).ln() / (1.0 - p_table).ln()).ceil();
+    (tables as usize).clamp(1, MAX_TABLES)
+}
+
+// Random hyperplane (SimHash) locality sensitive hashing for cosine
+// similarity. Candidates still have to be verified against the threshold.
+// Hyperplanes are drawn on the first insert, once the dimension is known.
+pub struct LshIndex {
+    tables: usize,
+    hyperplanes: Vec<Vec<Vec<f32>>>,
+    buckets: Vec<HashMap<u16, Vec<usize>>>,
+}
+
+impl LshIndex {
+    pub fn new(threshold: f32) -> LshIndex {
+        let tables = tables_for_threshold(threshold);
+
+        LshIndex {
+            tables,
+            hyperplanes: vec![],
+            buckets: vec![HashMap::new(); tables],
+        }
+    }
+
+    fn init_hyperplanes(&mut self, dim: usize) {
+        let mut rng = StdRng::seed_from_u64(SEED);
+
+        self.hyperplanes = (0..self.tables)
+            .map(|_| {
+                (0..BITS_PER_TABLE)
+                    .map(|_| (0..dim).map(|_| gaussian(&mut rng)).collect())
+                    .collect()
+            })
+            .collect();
+    }
+
+    fn signatures(&self, vector: &[f32]) -> Vec<u16> {
+        self.hyperplanes
+            .iter()
+            .map(|table| {
+                table
+                    .iter()
+                    .enumerate()
+                    .fold(0_u16, |signature, (bit, plane)| {
+                        if dot(plane, vector) >= 0.0 {
+                            signature | (1 << bit)
+                        } else {
+                            signature
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, item: usize, vector: &[f32]) {
+        if self.hyperplanes.is_empty() {
+            self.init_hyperplanes(vector.len());
+        }
+
+        for (table, signature) in self.signatures(vector).into_iter().enumerate() {
+            self.buckets[table].entry(signature).or_default().push(item);
+        }
+    }
+
+    // Removes an item inserted with `vector`, which must be the vector it was
+    // inserted with, so that it is found in the same buckets.
+    pub fn remove(&mut self, item: usize, vector: &[f32]) {
+        if self.hyperplanes.is_empty() {
+            return;
+        }
+
+        for (table, signature) in self.signatures(vector).into_iter().enumerate() {
+            if let Some(bucket) = self.buckets[table].get_mut(&signature) {
+                bucket.retain(|other| *other != item);
+                if bucket.is_empty() {
+                    self.buckets[table].remove(&signature);
+                }
+            }
+        }
+    }
+
+    pub fn candidates(&self, vector: &[f32]) -> Vec<usize> {
+        if self.hyperplanes.is_empty() {
+            return vec![];
+        }
+
+        let mut candidates: Vec<usize> = self
+            .signatures(vector)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(table, signature)| self.buckets[table].get(&signature))
+            .flatten()
+            .copied()
+            .collect();
+
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DuplicateGroup {
+    pub canonical: usize,
+    pub duplicates: Vec<(usize, f32)>,
+}
+
+// Groups vectors at or above the threshold around a canonical member.
+// Vectors are visited in order: each joins the most similar canonical member
+// it matches, or becomes a canonical member itself. Only canonical members
+// are hashed, so exact duplicates do not pile up in one bucket, and every
+// duplicate is reported with its similarity to its canonical member, which is
+// at least the threshold. Duplicates are not compared with each other, so two
+// duplicates of the same canonical member may be less similar than that.
+pub fn duplicate_groups<V: AsRef<[f32]>>(vectors: &[V], threshold: f32) -> Vec<DuplicateGroup> {
+    let mut lsh = LshIndex::new(threshold);
+    let mut groups: Vec<DuplicateGroup> = vec![];
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+
+    for (i, vector) in vectors.iter().map(AsRef::as_ref).enumerate() {
+        let best = lsh
+            .candidates(vector)
+            .into_iter()
+            .map(|j| (j, dot(vector, vectors[j].as_ref())))
+            .filter(|(_, score)| *score >= threshold)
+            .fold(None, |best: Option<(usize, f32)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            });
+
+        match best {
+            Some((canonical, score)) => {
+                let group = *group_of.entry(canonical).or_insert_with(|| {
+                    groups.push(DuplicateGroup {
+                        canonical,
+                        duplicates: vec![],
+                    });
+                    groups.len() - 1
+                });
+                groups[group].duplicates.push((i, score));
+            }
+            None => lsh.insert(i, vector),
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(v: Vec<f32>) -> Vec<f32> {
+        let norm = dot(&v, &v).sqrt();
+        v.into_iter().map(|x| x / norm).collect()
+    }
+
+    #[test]
+    fn test_tables_for_threshold() {
+        assert_eq!(tables_for_threshold(1.0), 1);
+        assert!(tables_for_threshold(0.95) < tables_for_threshold(0.9));
+        assert_eq!(tables_for_threshold(0.1), MAX_TABLES);
+    }
+
+    #[test]
+    fn test_lsh_candidates_include_identical_vectors() {
+        let v = normalized(vec![0.3, -0.2, 0.9, 0.1]);
+        let mut lsh = LshIndex::new(0.95);
+        assert!(lsh.candidates(&v).is_empty());
+
+        lsh.insert(7, &v);
+
+        assert_eq!(lsh.candidates(&v), vec![7]);
+    }
+
+    #[test]
+    fn test_lsh_remove() {
+        let (v, w) = (
+            normalized(vec![0.3, -0.2, 0.9, 0.1]),
+            normalized(vec![-0.5, 0.8, 0.1, 0.2]),
+        );
+        let mut lsh = LshIndex::new(0.95);
+        lsh.insert(1, &v);
+        lsh.insert(2, &v);
+
+        lsh.remove(1, &v);
+        lsh.insert(1, &w);
+        assert_eq!(lsh.candidates(&v), vec![2]);
+        assert_eq!(lsh.candidates(&w), vec![1]);
+
+        // Emptied buckets are dropped, leaving only the bucket of `w`.
+        lsh.remove(2, &v);
+        assert!(lsh.candidates(&v).is_empty());
+        assert!(lsh.buckets.iter().all(|buckets| buckets.len() == 1));
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let vectors = vec![
+            normalized(vec![1.0, 0.0, 0.0]),
+            normalized(vec![0.0, 1.0, 0.0]),
+            normalized(vec![1.0, 0.01, 0.0]),
+            normalized(vec![0.0, 0.0, 1.0]),
+            normalized(vec![0.0, 1.0, 0.02]),
+            normalized(vec![1.0, 0.0, 0.01]),
+        ];
+
+        let groups = duplicate_groups(&vectors, 0.99);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].canonical, 0);
+        assert_eq!(
+            groups[0]
+                .duplicates
+                .iter()
+                .map(|d| d.0)
+                .collect::<Vec<usize>>(),
+            vec![2, 5]
+        );
+        assert_eq!(groups[1].canonical, 1);
+        assert_eq!(groups[1].duplicates.len(), 1);
+        assert!(groups[1].duplicates[0].1 > 0.99);
+    }
+
+    #[test]
+    fn test_duplicate_groups_do_not_chain() {
+        let vectors: Vec<Vec<f32>> = (0..4)
+            .map(|i| {
+                let angle = i as f32 * 0.1;
+                vec![angle.cos(), angle.sin()]
+            })
+            .collect();
+
+        // Neighbours are within the threshold, but 2 is not within it of 0,
+        // so it starts a group of its own instead of extending a chain.
+        let groups = duplicate_groups(&vectors, 0.99);
+        let members: Vec<(usize, Vec<usize>)> = groups
+            .iter()
+            .map(|group| {
+                (
+                    group.canonical,
+                    group.duplicates.iter().map(|d| d.0).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(members, vec![(0, vec![1]), (2, vec![3])]);
+        assert!(groups
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .all(|(_, score)| *score >= 0.99));
+
+        let identical = vec![vec![1.0, 0.0]; 100];
+        let groups = duplicate_groups(&identical, 0.99);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].duplicates.len(), 99);
+    }
+}
@@ -1,27 +1,87 @@
+mod anomaly;
+mod chunking;
+mod classifier;
+mod cluster;
+mod embedding_cache;
+mod evaluation;
+mod export;
+mod import;
+mod jobs;
+mod lsh;
+mod mapped;
+mod matrix;
+mod names;
+mod ndjson;
+mod preprocess;
+mod projection;
+mod quantize;
 mod sent_transform;
+mod snapshot;
 mod vector_index;
 
 use actix_cors::Cors;
 use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::http::header;
 use actix_web::middleware::{Compress, Logger};
 use actix_web::{
-    delete, get, post, put, web, App, HttpResponse, HttpResponseBuilder, HttpServer, Responder,
-    Result,
+    delete, get, post, put, web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer,
+    Responder, Result,
 };
+use anomaly::AnomalyMethod;
+use chunking::{chunk_text_bodies, ChunkStrategy};
+use classifier::{zero_shot, ClassifierRegistry};
+use cluster::{ClusterCount, KMeansMethod, KMeansParams, Linkage};
+use embedding_cache::EmbeddingCache;
+use evaluation::{ApproximateMethod, Judgement, RankingMethod};
+use export::{ExportFormat, Exporter};
+use futures_util::StreamExt;
+use import::{
+    normalize_embeddings, parse_arrow, parse_ids, parse_jsonl, parse_npy, parse_texts, ImportFormat,
+};
+use jobs::{Job, JobError, JobRegistry, JobReport, JobStatus};
+use mapped::MappedStore;
+use ndjson::{parse_text_body, LineSplitter, MAX_LINE_LENGTH};
+use preprocess::Pipeline;
+use projection::{ProjectionMethod, TsneParams};
 use sent_transform::{
-    compute_normalized_embedding, compute_normalized_embeddings, load_model, SentenceTransformer,
+    count_tokens, count_wordpieces, embedding_dimensions, load_max_sequence_length, load_model,
+    load_tokenizer, paired_similarities, similarity_matrix, top_k_similarities, IndexWithScore,
+    SentenceTokenizer, SentenceTransformer, BATCH_SIZE, SPECIAL_TOKENS,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use snapshot::SnapshotStore;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::sync::{Arc, Mutex, RwLock};
-use vector_index::{GuardedIndex, TextBody};
+use vector_index::{
+    search_collapsed, validate_oversample, Deduplicated, DuplicateGroup, GuardedIndex,
+    IndexSettings, QuantizationConfig, TextBody, DEFAULT_OVERSAMPLE,
+};
 
 #[derive(Deserialize)]
 struct QueryParams {
     q: String,
     n: Option<String>,
     method: Option<String>,
+    collapse: Option<bool>,
+    oversample: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IngestParams {
+    chunk: Option<String>,
+    chunk_size: Option<String>,
+    chunk_overlap: Option<String>,
+    strict: Option<bool>,
+    #[serde(rename = "async")]
+    run_async: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct WeightsParams {
+    tokens: Option<bool>,
+    strict: Option<bool>,
 }
 
 const DEFAULT_NRESULTS: &str = "3";
@@ -35,6 +95,66 @@ fn parse_nresults(nparam: Option<String>) -> Result<usize, HttpResponse> {
     })
 }
 
+// Token windows are sized in wordpieces and by default fill the model's
+// maximum sequence length, less the special tokens, so chunks are never
+// truncated. The default overlap is a quarter of the window.
+fn parse_chunk_strategy(
+    params: &IngestParams,
+    max_sequence_length: usize,
+) -> Result<Option<ChunkStrategy>, HttpResponse> {
+    let max_chunk_size = max_sequence_length - SPECIAL_TOKENS;
+    let parse_usize = |name: &str, param: &Option<String>, default: usize| match param {
+        Some(param) => param.parse::<usize>().map_err(|err| {
+            resp_error(
+                HttpResponse::BadRequest(),
+                format!("Could not convert {name} query param: {err}"),
+            )
+        }),
+        None => Ok(default),
+    };
+
+    match params.chunk.as_deref() {
+        None => Ok(None),
+        Some("sentence") => Ok(Some(ChunkStrategy::Sentence)),
+        Some("tokens") => {
+            let size = parse_usize("chunk_size", &params.chunk_size, max_chunk_size)?;
+            if size > max_chunk_size {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!(
+                        "Chunk size ({size}) must be at most {max_chunk_size} tokens, the model's maximum sequence length without special tokens"
+                    ),
+                ));
+            }
+            let overlap = parse_usize("chunk_overlap", &params.chunk_overlap, size / 4)?;
+
+            ChunkStrategy::token_window(size, overlap)
+                .map(Some)
+                .map_err(|error| resp_error(HttpResponse::BadRequest(), error))
+        }
+        Some(param) => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid chunk '{param}'. Must be 'sentence' or 'tokens'"),
+        )),
+    }
+}
+
+fn maybe_chunk(
+    state: &ServerState,
+    text_bodies: Vec<TextBody>,
+    strategy: Option<ChunkStrategy>,
+) -> Result<Vec<TextBody>, String> {
+    match strategy {
+        Some(strategy) => {
+            let tokenizer = state.tokenizer.lock().unwrap();
+            chunk_text_bodies(&text_bodies, strategy, |words| {
+                count_wordpieces(&tokenizer, words)
+            })
+        }
+        None => Ok(text_bodies),
+    }
+}
+
 enum SearchMethod {
     Cosine,
     ExemplarSVM,
@@ -70,16 +190,50 @@ async fn query_index(
         Err(resp) => return resp,
     };
 
+    let collapse = params.collapse.unwrap_or(false);
+
+    let oversample = match params.oversample.as_deref().map(str::parse::<f32>) {
+        Some(Ok(oversample)) => Some(oversample),
+        Some(Err(err)) => {
+            return resp_error(
+                HttpResponse::BadRequest(),
+                format!("Could not convert oversample query param: {err}"),
+            )
+        }
+        None => None,
+    };
+    if let Some(Err(error)) = oversample.map(validate_oversample) {
+        return resp_error(HttpResponse::BadRequest(), error);
+    }
+
     let index_name = index_name.to_string();
     let cache = state.cache.read().unwrap();
     let model = state.model.lock().unwrap();
+    let mut embedding_cache = state.embedding_cache.lock().unwrap();
 
     match cache.get(&index_name) {
-        Some(index) => compute_normalized_embedding(&model, &params.q)
+        Some(index) => embedding_cache
+            .compute_normalized_embedding(&model, &index.pipeline().apply(&params.q))
             .map_err(|err| format!("Error computing embedding: {err}"))
-            .and_then(|query_embedding| match search_method {
-                SearchMethod::Cosine => index.search_knn(&query_embedding, n),
-                SearchMethod::ExemplarSVM => index.search_exemplar_svm(&query_embedding, n),
+            .and_then(|query_embedding| {
+                let search = |nsearch: usize| match (&search_method, &index.settings().quantization)
+                {
+                    (SearchMethod::Cosine, Some(quantization)) => index.search_quantized(
+                        &query_embedding,
+                        nsearch,
+                        oversample.unwrap_or(quantization.oversample),
+                    ),
+                    (SearchMethod::Cosine, None) => index.search_knn(&query_embedding, nsearch),
+                    (SearchMethod::ExemplarSVM, _) => {
+                        index.search_exemplar_svm(&query_embedding, nsearch)
+                    }
+                };
+
+                if collapse {
+                    search_collapsed(n, index.len(), search)
+                } else {
+                    search(n)
+                }
             })
             .map_or_else(
                 |error| resp_error(HttpResponse::InternalServerError(), error),
@@ -92,10 +246,43 @@ async fn query_index(
     }
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CreateIndexBody {
+    TextBodies(Vec<TextBody>),
+    WithSettings {
+        settings: IndexSettings,
+        #[serde(default)]
+        texts: Vec<TextBody>,
+    },
+}
+
 #[derive(Serialize)]
 struct RespIndex {
     index: String,
     size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<Vec<RespTokens>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    settings: Option<IndexSettings>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deduplicated: Vec<Deduplicated>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    read_only: bool,
+}
+
+#[derive(Serialize)]
+struct RespTokens {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    tokens: usize,
+    truncated: bool,
+}
+
+#[derive(Serialize)]
+struct RespWeights {
+    embeddings: Vec<Vec<f32>>,
+    tokens: Vec<RespTokens>,
 }
 
 #[derive(Serialize)]
@@ -106,17 +293,121 @@ struct RespError {
 
 fn compute_text_bodies_embeddings(
     model: &SentenceTransformer,
+    embedding_cache: &mut EmbeddingCache,
+    pipeline: &Pipeline,
     text_bodies: &[TextBody],
 ) -> Result<Vec<Vec<f32>>, String> {
-    let text_strs: Vec<String> = text_bodies.iter().map(|tb| tb.text.clone()).collect();
+    let text_strs: Vec<String> = text_bodies
+        .iter()
+        .map(|tb| pipeline.apply(&tb.text))
+        .collect();
     let text_strs: Vec<&str> = text_strs.iter().map(|s| s.as_str()).collect();
 
-    compute_normalized_embeddings(model, &text_strs)
+    embedding_cache
+        .compute_normalized_embeddings(model, &text_strs)
         .map_err(|err_code| format!("Could not compute embeddings: {err_code}"))
 }
 
+fn count_text_bodies_tokens(
+    tokenizer: &SentenceTokenizer,
+    max_sequence_length: usize,
+    pipeline: &Pipeline,
+    text_bodies: &[TextBody],
+) -> Vec<RespTokens> {
+    let text_strs: Vec<String> = text_bodies
+        .iter()
+        .map(|tb| pipeline.apply(&tb.text))
+        .collect();
+    let text_strs: Vec<&str> = text_strs.iter().map(|s| s.as_str()).collect();
+
+    count_tokens(tokenizer, &text_strs, max_sequence_length)
+        .into_iter()
+        .zip(text_bodies.iter())
+        .map(|(count, tb)| RespTokens {
+            id: Some(tb.id.clone()),
+            tokens: count.tokens,
+            truncated: count.truncated,
+        })
+        .collect()
+}
+
+fn count_strs_tokens(
+    tokenizer: &SentenceTokenizer,
+    max_sequence_length: usize,
+    strs: &[&str],
+) -> Vec<RespTokens> {
+    count_tokens(tokenizer, strs, max_sequence_length)
+        .into_iter()
+        .map(|count| RespTokens {
+            id: None,
+            tokens: count.tokens,
+            truncated: count.truncated,
+        })
+        .collect()
+}
+
+fn reject_truncated(tokens: &[RespTokens], max_sequence_length: usize) -> Result<(), HttpResponse> {
+    let truncated: Vec<String> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.truncated)
+        .map(|(idx, t)| t.id.clone().unwrap_or(idx.to_string()))
+        .collect();
+
+    if truncated.is_empty() {
+        return Ok(());
+    }
+
+    Err(resp_error(
+        HttpResponse::BadRequest(),
+        format!(
+            "Inputs exceed the maximum sequence length of {max_sequence_length} tokens: {}",
+            truncated.join(", ")
+        ),
+    ))
+}
+
 fn ok_resp_index(index: String, size: usize) -> HttpResponse {
-    HttpResponse::Ok().json(RespIndex { index, size })
+    HttpResponse::Ok().json(RespIndex {
+        index,
+        size,
+        tokens: None,
+        settings: None,
+        deduplicated: vec![],
+        read_only: false,
+    })
+}
+
+fn ok_resp_index_with_tokens(
+    index: String,
+    size: usize,
+    tokens: Vec<RespTokens>,
+    deduplicated: Vec<Deduplicated>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(RespIndex {
+        index,
+        size,
+        tokens: Some(tokens),
+        settings: None,
+        deduplicated,
+        read_only: false,
+    })
+}
+
+fn ok_resp_index_with_settings(
+    index: String,
+    size: usize,
+    settings: IndexSettings,
+    read_only: bool,
+) -> HttpResponse {
+    HttpResponse::Ok().json(RespIndex {
+        index,
+        size,
+        tokens: None,
+        settings: Some(settings),
+        deduplicated: vec![],
+        read_only,
+    })
 }
 
 fn resp_error(mut status: HttpResponseBuilder, error: String) -> HttpResponse {
@@ -126,9 +417,28 @@ fn resp_error(mut status: HttpResponseBuilder, error: String) -> HttpResponse {
 #[post("/index/{index_name}")]
 async fn index_create(
     index_name: web::Path<String>,
-    maybe_text_bodies: Option<web::Json<Vec<TextBody>>>,
+    ingest_params: web::Query<IngestParams>,
+    maybe_body: Option<web::Json<CreateIndexBody>>,
     state: web::Data<ServerState>,
 ) -> impl Responder {
+    let chunk_strategy = match parse_chunk_strategy(&ingest_params, state.max_sequence_length) {
+        Ok(strategy) => strategy,
+        Err(resp) => return resp,
+    };
+
+    let (settings, text_bodies) = match maybe_body.map(|body| body.into_inner()) {
+        Some(CreateIndexBody::TextBodies(text_bodies)) => {
+            (IndexSettings::default(), Some(text_bodies))
+        }
+        Some(CreateIndexBody::WithSettings { settings, texts }) => (settings, Some(texts)),
+        None => (IndexSettings::default(), None),
+    };
+
+    let index = match GuardedIndex::empty().with_settings(settings) {
+        Ok(index) => index,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
     let index_name = index_name.to_string();
     let mut cache = state.cache.write().unwrap();
     if cache.contains_key(&index_name) {
@@ -138,25 +448,59 @@ async fn index_create(
         );
     }
 
-    match maybe_text_bodies {
+    match text_bodies {
+        Some(text_bodies) if ingest_params.run_async.unwrap_or(false) => {
+            let index = Arc::new(index);
+            cache.insert(index_name.clone(), Arc::clone(&index));
+
+            accepted_job(queue_ingest_job(
+                state.clone(),
+                &index_name,
+                index,
+                text_bodies,
+                chunk_strategy,
+                ingest_params.strict.unwrap_or(false),
+            ))
+        }
         Some(text_bodies) => {
-            let text_bodies = text_bodies.to_vec();
+            let mut text_bodies = match maybe_chunk(&state, text_bodies, chunk_strategy) {
+                Ok(text_bodies) => text_bodies,
+                Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+            };
+            let tokens = count_text_bodies_tokens(
+                &state.tokenizer.lock().unwrap(),
+                state.max_sequence_length,
+                index.pipeline(),
+                &text_bodies,
+            );
+            if ingest_params.strict.unwrap_or(false) {
+                if let Err(resp) = reject_truncated(&tokens, state.max_sequence_length) {
+                    return resp;
+                }
+            }
+
             let model = state.model.lock().unwrap();
+            let mut embedding_cache = state.embedding_cache.lock().unwrap();
 
-            compute_text_bodies_embeddings(&model, &text_bodies)
-                .and_then(|embeddings| GuardedIndex::new(text_bodies, embeddings))
-                .map_or_else(
-                    |error| resp_error(HttpResponse::InternalServerError(), error),
-                    |index| {
-                        let n = index.len();
-                        cache.insert(index_name.clone(), index);
+            compute_text_bodies_embeddings(
+                &model,
+                &mut embedding_cache,
+                index.pipeline(),
+                &text_bodies,
+            )
+            .and_then(|mut embeddings| index.append_contents(&mut text_bodies, &mut embeddings))
+            .map_or_else(
+                |error| resp_error(HttpResponse::InternalServerError(), error),
+                |deduplicated| {
+                    let n = index.len();
+                    cache.insert(index_name.clone(), Arc::new(index));
 
-                        ok_resp_index(index_name, n)
-                    },
-                )
+                    ok_resp_index_with_tokens(index_name, n, tokens, deduplicated)
+                },
+            )
         }
         None => {
-            cache.insert(index_name.clone(), GuardedIndex::empty());
+            cache.insert(index_name.clone(), Arc::new(index));
             ok_resp_index(index_name, 0)
         }
     }
@@ -167,7 +511,12 @@ async fn index_read(index_name: web::Path<String>, state: web::Data<ServerState>
     let index_name = index_name.to_string();
 
     match state.cache.read().unwrap().get(&index_name) {
-        Some(index) => ok_resp_index(index_name, index.len()),
+        Some(index) => ok_resp_index_with_settings(
+            index_name,
+            index.len(),
+            index.settings().clone(),
+            index.is_read_only(),
+        ),
         None => resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
     }
 }
@@ -175,26 +524,67 @@ async fn index_read(index_name: web::Path<String>, state: web::Data<ServerState>
 #[put("/index/{index_name}")]
 async fn index_update(
     index_name: web::Path<String>,
+    ingest_params: web::Query<IngestParams>,
     text_bodies: web::Json<Vec<TextBody>>,
     state: web::Data<ServerState>,
 ) -> HttpResponse {
+    let chunk_strategy = match parse_chunk_strategy(&ingest_params, state.max_sequence_length) {
+        Ok(strategy) => strategy,
+        Err(resp) => return resp,
+    };
+
     let index_name = index_name.to_string();
     let cache = state.cache.write().unwrap();
     match cache.get(&index_name) {
+        Some(index) if index.is_read_only() => resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} is read-only"),
+        ),
+        Some(index) if ingest_params.run_async.unwrap_or(false) => accepted_job(queue_ingest_job(
+            state.clone(),
+            &index_name,
+            Arc::clone(index),
+            text_bodies.into_inner(),
+            chunk_strategy,
+            ingest_params.strict.unwrap_or(false),
+        )),
         Some(index) => {
-            let mut text_bodies = text_bodies.to_vec();
+            let mut text_bodies = match maybe_chunk(&state, text_bodies.to_vec(), chunk_strategy) {
+                Ok(text_bodies) => text_bodies,
+                Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+            };
+            let tokens = count_text_bodies_tokens(
+                &state.tokenizer.lock().unwrap(),
+                state.max_sequence_length,
+                index.pipeline(),
+                &text_bodies,
+            );
+            if ingest_params.strict.unwrap_or(false) {
+                if let Err(resp) = reject_truncated(&tokens, state.max_sequence_length) {
+                    return resp;
+                }
+            }
+
             let model = state.model.lock().unwrap();
+            let mut embedding_cache = state.embedding_cache.lock().unwrap();
 
-            compute_text_bodies_embeddings(&model, &text_bodies)
-                .and_then(|embeddings| {
-                    let mut mut_embeddings = embeddings;
+            compute_text_bodies_embeddings(
+                &model,
+                &mut embedding_cache,
+                index.pipeline(),
+                &text_bodies,
+            )
+            .and_then(|embeddings| {
+                let mut mut_embeddings = embeddings;
 
-                    index.append_contents(&mut text_bodies, &mut mut_embeddings)
-                })
-                .map_or_else(
-                    |error| resp_error(HttpResponse::InternalServerError(), error),
-                    |()| ok_resp_index(index_name, index.len()),
-                )
+                index.append_contents(&mut text_bodies, &mut mut_embeddings)
+            })
+            .map_or_else(
+                |error| resp_error(HttpResponse::InternalServerError(), error),
+                |deduplicated| {
+                    ok_resp_index_with_tokens(index_name, index.len(), tokens, deduplicated)
+                },
+            )
         }
         None => resp_error(
             HttpResponse::NotFound(),
@@ -203,6 +593,361 @@ async fn index_update(
     }
 }
 
+#[derive(Serialize)]
+struct RespLineError {
+    line: usize,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct RespStream {
+    index: String,
+    size: usize,
+    received: usize,
+    indexed: usize,
+    errors: Vec<RespLineError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deduplicated: Vec<Deduplicated>,
+}
+
+fn ingest_stream_line(
+    line: usize,
+    raw: &[u8],
+    pending: &mut Vec<(usize, TextBody)>,
+    report: &mut RespStream,
+) {
+    match parse_text_body(raw) {
+        Ok(Some(text_body)) => {
+            report.received += 1;
+            pending.push((line, text_body));
+        }
+        Ok(None) => (),
+        Err(error) => report.errors.push(RespLineError { line, error }),
+    }
+}
+
+struct BatchOutcome {
+    indexed: usize,
+    errors: Vec<(usize, String)>,
+    deduplicated: Vec<Deduplicated>,
+}
+
+// Chunks, checks and embeds a batch of text bodies tagged with their position
+// in the request, then appends them to `index`. Per-position errors are
+// reported in the outcome; an `Err` means nothing in the batch was indexed.
+fn ingest_batch(
+    state: &ServerState,
+    index: &GuardedIndex,
+    batch: Vec<(usize, TextBody)>,
+    chunk_strategy: Option<ChunkStrategy>,
+    strict: bool,
+) -> Result<BatchOutcome, String> {
+    let mut positions: Vec<usize> = vec![];
+    let mut text_bodies: Vec<TextBody> = vec![];
+    let mut errors: Vec<(usize, String)> = vec![];
+    for (position, text_body) in batch {
+        match maybe_chunk(state, vec![text_body], chunk_strategy) {
+            Ok(chunks) => {
+                for chunk in chunks {
+                    positions.push(position);
+                    text_bodies.push(chunk);
+                }
+            }
+            Err(error) => errors.push((position, error)),
+        }
+    }
+
+    if strict {
+        let tokens = count_text_bodies_tokens(
+            &state.tokenizer.lock().unwrap(),
+            state.max_sequence_length,
+            index.pipeline(),
+            &text_bodies,
+        );
+
+        let mut truncated: Vec<usize> = vec![];
+        for (position, count) in positions.iter().zip(tokens.iter()) {
+            if count.truncated && !truncated.contains(position) {
+                truncated.push(*position);
+                errors.push((
+                    *position,
+                    format!(
+                        "Input exceeds the maximum sequence length of {} tokens",
+                        state.max_sequence_length
+                    ),
+                ));
+            }
+        }
+
+        (positions, text_bodies) = positions
+            .into_iter()
+            .zip(text_bodies)
+            .filter(|(position, _)| !truncated.contains(position))
+            .unzip();
+    }
+
+    if text_bodies.is_empty() {
+        return Ok(BatchOutcome {
+            indexed: 0,
+            errors,
+            deduplicated: vec![],
+        });
+    }
+
+    let model = state.model.lock().unwrap();
+    let mut embedding_cache = state.embedding_cache.lock().unwrap();
+    let ids: Vec<String> = text_bodies.iter().map(|t| t.id.clone()).collect();
+
+    compute_text_bodies_embeddings(&model, &mut embedding_cache, index.pipeline(), &text_bodies)
+        .and_then(|mut embeddings| index.append_contents(&mut text_bodies, &mut embeddings))
+        .map(|deduplicated| {
+            // Positions count as indexed if any of their chunks was appended
+            // rather than dropped or merged as a duplicate.
+            let duplicates: HashSet<&str> = deduplicated.iter().map(|d| d.id.as_str()).collect();
+            let mut indexed: Vec<usize> = positions
+                .iter()
+                .zip(&ids)
+                .filter(|(_, id)| !duplicates.contains(id.as_str()))
+                .map(|(position, _)| *position)
+                .collect();
+            indexed.dedup();
+
+            BatchOutcome {
+                indexed: indexed.len(),
+                errors,
+                deduplicated,
+            }
+        })
+}
+
+fn flush_stream_batch(
+    state: &ServerState,
+    index: &GuardedIndex,
+    pending: &mut Vec<(usize, TextBody)>,
+    chunk_strategy: Option<ChunkStrategy>,
+    strict: bool,
+    report: &mut RespStream,
+) {
+    let mut lines: Vec<usize> = pending.iter().map(|(line, _)| *line).collect();
+    let batch: Vec<(usize, TextBody)> = std::mem::take(pending);
+
+    match ingest_batch(state, index, batch, chunk_strategy, strict) {
+        Ok(outcome) => {
+            report.indexed += outcome.indexed;
+            report.errors.extend(
+                outcome
+                    .errors
+                    .into_iter()
+                    .map(|(line, error)| RespLineError { line, error }),
+            );
+            report.deduplicated.extend(outcome.deduplicated);
+        }
+        Err(error) => {
+            lines.dedup();
+            for line in lines {
+                report.errors.push(RespLineError {
+                    line,
+                    error: error.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[post("/index/{index_name}/stream")]
+async fn index_stream(
+    index_name: web::Path<String>,
+    ingest_params: web::Query<IngestParams>,
+    mut payload: web::Payload,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let chunk_strategy = match parse_chunk_strategy(&ingest_params, state.max_sequence_length) {
+        Ok(strategy) => strategy,
+        Err(resp) => return resp,
+    };
+    let strict = ingest_params.strict.unwrap_or(false);
+
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => {
+            return resp_error(
+                HttpResponse::NotFound(),
+                format!("{index_name} is not found"),
+            )
+        }
+    };
+    if index.is_read_only() {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} is read-only"),
+        );
+    }
+
+    let mut report = RespStream {
+        index: index_name,
+        size: 0,
+        received: 0,
+        indexed: 0,
+        errors: vec![],
+        deduplicated: vec![],
+    };
+    let mut splitter = LineSplitter::new(MAX_LINE_LENGTH);
+    let mut pending: Vec<(usize, TextBody)> = vec![];
+
+    while let Some(bytes) = payload.next().await {
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return resp_error(
+                    HttpResponse::BadRequest(),
+                    format!(
+                        "Error reading payload after {} lines ({} indexed): {err}",
+                        report.received, report.indexed
+                    ),
+                )
+            }
+        };
+
+        let lines = match splitter.push(&bytes) {
+            Ok(lines) => lines,
+            Err(err) => {
+                return resp_error(
+                    HttpResponse::BadRequest(),
+                    format!(
+                        "Error reading payload after {} lines ({} indexed): {err}",
+                        report.received, report.indexed
+                    ),
+                )
+            }
+        };
+        for (line, raw) in lines {
+            ingest_stream_line(line, &raw, &mut pending, &mut report);
+        }
+
+        if pending.len() >= BATCH_SIZE {
+            flush_stream_batch(
+                &state,
+                &index,
+                &mut pending,
+                chunk_strategy,
+                strict,
+                &mut report,
+            );
+        }
+    }
+
+    if let Some((line, raw)) = splitter.finish() {
+        ingest_stream_line(line, &raw, &mut pending, &mut report);
+    }
+    flush_stream_batch(
+        &state,
+        &index,
+        &mut pending,
+        chunk_strategy,
+        strict,
+        &mut report,
+    );
+
+    report.errors.sort_by_key(|e| e.line);
+    report.size = index.len();
+
+    HttpResponse::Ok().json(report)
+}
+
+fn run_ingest_job(
+    state: &ServerState,
+    index: &GuardedIndex,
+    job: &Job,
+    text_bodies: Vec<TextBody>,
+    chunk_strategy: Option<ChunkStrategy>,
+    strict: bool,
+) {
+    let items: Vec<(usize, TextBody)> = text_bodies.into_iter().enumerate().collect();
+
+    for batch in items.chunks(BATCH_SIZE) {
+        if job.is_cancelled() {
+            job.finish(JobStatus::Cancelled, index.len());
+            return;
+        }
+
+        match ingest_batch(state, index, batch.to_vec(), chunk_strategy, strict) {
+            // Items rejected in strict mode or merged into duplicates were
+            // not appended, so they only show up in the errors or duplicates.
+            Ok(outcome) => job.record_progress(
+                outcome.indexed,
+                outcome
+                    .errors
+                    .into_iter()
+                    .map(|(item, error)| JobError { item, error })
+                    .collect(),
+                outcome.deduplicated,
+            ),
+            Err(error) => {
+                job.fail(batch[0].0, error, index.len());
+                return;
+            }
+        }
+    }
+
+    job.finish(JobStatus::Completed, index.len());
+}
+
+fn queue_ingest_job(
+    state: web::Data<ServerState>,
+    index_name: &str,
+    index: Arc<GuardedIndex>,
+    text_bodies: Vec<TextBody>,
+    chunk_strategy: Option<ChunkStrategy>,
+    strict: bool,
+) -> JobReport {
+    let job = state.jobs.submit(index_name, text_bodies.len());
+    let report = job.report();
+
+    // The task owns its own handle on the state, which also holds the queue.
+    let worker_state = state.clone();
+    let queued = Arc::clone(&job);
+    state.jobs.run(&job, move || {
+        run_ingest_job(
+            &worker_state,
+            &index,
+            &queued,
+            text_bodies,
+            chunk_strategy,
+            strict,
+        )
+    });
+
+    report
+}
+
+fn accepted_job(report: JobReport) -> HttpResponse {
+    HttpResponse::Accepted().json(report)
+}
+
+#[get("/jobs/{job_id}")]
+async fn job_read(job_id: web::Path<u64>, state: web::Data<ServerState>) -> HttpResponse {
+    let job_id = job_id.into_inner();
+
+    match state.jobs.get(job_id) {
+        Some(job) => HttpResponse::Ok().json(job.report()),
+        None => resp_error(HttpResponse::NotFound(), format!("Job {job_id} not found")),
+    }
+}
+
+#[delete("/jobs/{job_id}")]
+async fn job_cancel(job_id: web::Path<u64>, state: web::Data<ServerState>) -> HttpResponse {
+    let job_id = job_id.into_inner();
+
+    match state.jobs.get(job_id) {
+        Some(job) => {
+            job.cancel();
+            HttpResponse::Ok().json(job.report())
+        }
+        None => resp_error(HttpResponse::NotFound(), format!("Job {job_id} not found")),
+    }
+}
+
 #[delete("/index/{index_name}")]
 async fn index_delete(
     index_name: web::Path<String>,
@@ -212,58 +957,1732 @@ async fn index_delete(
     let mut cache = state.cache.write().unwrap();
 
     match cache.remove(&index_name) {
-        Some(index) => ok_resp_index(index_name, index.len()),
+        Some(index) => {
+            // Running ingest jobs would otherwise keep embedding into an index
+            // that can no longer be reached.
+            state.jobs.cancel_index(&index_name);
+            ok_resp_index(index_name, index.len())
+        }
         None => resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
     }
 }
 
 #[post("/weights")]
 async fn compute_weights(
+    params: web::Query<WeightsParams>,
     texts: web::Json<Vec<String>>,
     state: web::Data<ServerState>,
 ) -> HttpResponse {
-    let model = state.model.lock().unwrap();
-
     let strs = texts.to_vec();
     let strs: Vec<&str> = strs.iter().map(|s| s.as_str()).collect();
 
-    compute_normalized_embeddings(&model, &strs).map_or_else(
-        |error| {
-            resp_error(
+    let tokens = count_strs_tokens(
+        &state.tokenizer.lock().unwrap(),
+        state.max_sequence_length,
+        &strs,
+    );
+    if params.strict.unwrap_or(false) {
+        if let Err(resp) = reject_truncated(&tokens, state.max_sequence_length) {
+            return resp;
+        }
+    }
+    let report_tokens = params.tokens.unwrap_or(false);
+
+    let model = state.model.lock().unwrap();
+    let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+    embedding_cache
+        .compute_normalized_embeddings(&model, &strs)
+        .map_or_else(
+            |error| {
+                resp_error(
+                    HttpResponse::InternalServerError(),
+                    format!("Could not compute embeddings: {error}"),
+                )
+            },
+            |embeddings| {
+                if report_tokens {
+                    HttpResponse::Ok().json(RespWeights { embeddings, tokens })
+                } else {
+                    HttpResponse::Ok().json(embeddings)
+                }
+            },
+        )
+}
+
+#[derive(Deserialize)]
+struct SimilarityRequest {
+    a: Vec<String>,
+    b: Option<Vec<String>>,
+    paired: Option<bool>,
+    top_k: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RespSimilarity {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scores: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<Vec<Vec<IndexWithScore>>>,
+}
+
+// The full matrix grows with the product of both lists, so larger
+// comparisons must ask for the `top_k` scores of each text instead.
+const MAX_SIMILARITY_MATRIX: usize = 1_000_000;
+
+#[post("/similarity")]
+async fn similarity(
+    request: web::Json<SimilarityRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let paired = request.paired.unwrap_or(false);
+    if paired && request.top_k.is_some() {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            String::from("paired and top_k cannot be combined"),
+        );
+    }
+
+    let matrix_size = request.a.len() * request.b.as_ref().unwrap_or(&request.a).len();
+    if !paired && request.top_k.is_none() && matrix_size > MAX_SIMILARITY_MATRIX {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!(
+                "A similarity matrix of {matrix_size} scores exceeds the maximum of \
+                 {MAX_SIMILARITY_MATRIX}. Use top_k to get the most similar texts instead"
+            ),
+        );
+    }
+
+    let a: Vec<&str> = request.a.iter().map(|s| s.as_str()).collect();
+    let b: Option<Vec<&str>> = request
+        .b
+        .as_ref()
+        .map(|b| b.iter().map(|s| s.as_str()).collect());
+
+    let embeddings = {
+        let model = state.model.lock().unwrap();
+        let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+        embedding_cache
+            .compute_normalized_embeddings(&model, &a)
+            .and_then(|a_embeddings| match &b {
+                Some(b) => embedding_cache
+                    .compute_normalized_embeddings(&model, b)
+                    .map(|b_embeddings| (a_embeddings, Some(b_embeddings))),
+                None => Ok((a_embeddings, None)),
+            })
+    };
+
+    let (a_embeddings, b_embeddings) = match embeddings {
+        Ok(embeddings) => embeddings,
+        Err(error) => {
+            return resp_error(
                 HttpResponse::InternalServerError(),
                 format!("Could not compute embeddings: {error}"),
             )
-        },
-        |embeddings| HttpResponse::Ok().json(embeddings),
+        }
+    };
+    // Without `b`, texts in `a` are compared with each other.
+    let b_embeddings = b_embeddings.as_ref().unwrap_or(&a_embeddings);
+
+    let resp = if paired {
+        paired_similarities(&a_embeddings, b_embeddings).map(|scores| RespSimilarity {
+            matrix: None,
+            scores: Some(scores),
+            top_k: None,
+        })
+    } else if let Some(k) = request.top_k {
+        top_k_similarities(&a_embeddings, b_embeddings, k).map(|top_k| RespSimilarity {
+            matrix: None,
+            scores: None,
+            top_k: Some(top_k),
+        })
+    } else {
+        similarity_matrix(&a_embeddings, b_embeddings).map(|matrix| RespSimilarity {
+            matrix: Some(matrix),
+            scores: None,
+            top_k: None,
+        })
+    };
+
+    resp.map_or_else(
+        |error| resp_error(HttpResponse::BadRequest(), error),
+        |resp| HttpResponse::Ok().json(resp),
     )
 }
 
-#[get("/")]
-async fn root() -> Result<NamedFile> {
-    Ok(NamedFile::open("root.html")?)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KParam {
+    Fixed(usize),
+    Auto(String),
 }
-struct ServerState {
-    model: Mutex<SentenceTransformer>,
-    cache: Arc<RwLock<HashMap<String, GuardedIndex>>>,
+
+#[derive(Deserialize)]
+struct ClusterRequest {
+    k: KParam,
+    k_min: Option<usize>,
+    k_max: Option<usize>,
+    method: Option<String>,
+    max_iter: Option<usize>,
+    batch_size: Option<usize>,
+    seed: Option<u64>,
 }
 
-const DEFAULT_MODEL_PATH: &str = "models/distiluse-base-multilingual-cased-converted";
-const DEFAULT_ADDRESS: &str = "0.0.0.0";
-const DEFAULT_PORT: u16 = 8000;
+const DEFAULT_CLUSTER_MAX_ITER: usize = 100;
+const DEFAULT_CLUSTER_BATCH_SIZE: usize = 1024;
+const CLUSTER_TOLERANCE: f32 = 1e-6;
+const MINIBATCH_THRESHOLD: usize = 10_000;
+const MAX_CLUSTER_MAX_ITER: usize = 1_000;
+const DEFAULT_K_MIN: usize = 2;
+const DEFAULT_K_MAX: usize = 10;
+const MAX_CLUSTERS: usize = 1_000;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+fn parse_cluster_count(
+    k: &KParam,
+    k_min: Option<usize>,
+    k_max: Option<usize>,
+    index_len: usize,
+) -> Result<ClusterCount, HttpResponse> {
+    let count = match k {
+        KParam::Fixed(k) => ClusterCount::Fixed(*k),
+        KParam::Auto(param) if param == "auto" => ClusterCount::Auto {
+            min: k_min.unwrap_or(DEFAULT_K_MIN),
+            max: k_max.unwrap_or(DEFAULT_K_MAX),
+        },
+        KParam::Auto(param) => {
+            return Err(resp_error(
+                HttpResponse::BadRequest(),
+                format!("Invalid k '{param}'. Must be a number or 'auto'"),
+            ))
+        }
+    };
 
-    let model_path = env::var("MODEL_PATH").unwrap_or(String::from(DEFAULT_MODEL_PATH));
+    let largest = match count {
+        ClusterCount::Fixed(k) => k,
+        ClusterCount::Auto { max, .. } => max,
+    };
+    if largest > MAX_CLUSTERS {
+        return Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("k must be at most {MAX_CLUSTERS}"),
+        ));
+    }
+    if largest > index_len {
+        return Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Cannot create {largest} clusters from {index_len} embeddings"),
+        ));
+    }
+
+    Ok(count)
+}
+
+fn parse_kmeans_method(
+    request: &ClusterRequest,
+    index_len: usize,
+) -> Result<KMeansMethod, HttpResponse> {
+    let minibatch = KMeansMethod::MiniBatch {
+        batch_size: request.batch_size.unwrap_or(DEFAULT_CLUSTER_BATCH_SIZE),
+    };
+
+    match request.method.as_deref() {
+        Some("kmeans") => Ok(KMeansMethod::Full),
+        Some("minibatch") => Ok(minibatch),
+        Some("auto") | None => Ok(if index_len > MINIBATCH_THRESHOLD {
+            minibatch
+        } else {
+            KMeansMethod::Full
+        }),
+        Some(param) => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid method '{param}'. Must be 'kmeans', 'minibatch' or 'auto'"),
+        )),
+    }
+}
+
+#[post("/index/{index_name}/cluster")]
+async fn index_cluster(
+    index_name: web::Path<String>,
+    request: web::Json<ClusterRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let method = match parse_kmeans_method(&request, index.len()) {
+        Ok(method) => method,
+        Err(resp) => return resp,
+    };
+
+    let count = match parse_cluster_count(&request.k, request.k_min, request.k_max, index.len()) {
+        Ok(count) => count,
+        Err(resp) => return resp,
+    };
+
+    let max_iter = request.max_iter.unwrap_or(DEFAULT_CLUSTER_MAX_ITER);
+    if max_iter > MAX_CLUSTER_MAX_ITER {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("max_iter must be at most {MAX_CLUSTER_MAX_ITER}"),
+        );
+    }
+
+    let params = KMeansParams {
+        method,
+        max_iter,
+        tolerance: CLUSTER_TOLERANCE,
+        seed: request.seed.unwrap_or(0),
+    };
+
+    let clustering = web::block(move || index.kmeans(count, &params)).await;
+
+    match clustering {
+        Ok(Ok(clustering)) => HttpResponse::Ok().json(clustering),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Clustering failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct HierarchyRequest {
+    linkage: Option<String>,
+    k: Option<KParam>,
+    k_min: Option<usize>,
+    k_max: Option<usize>,
+    seed: Option<u64>,
+}
+
+fn parse_linkage(linkage: Option<&str>) -> Result<Linkage, HttpResponse> {
+    match linkage {
+        Some("single") => Ok(Linkage::Single),
+        Some("complete") => Ok(Linkage::Complete),
+        Some("average") | None => Ok(Linkage::Average),
+        Some("ward") => Ok(Linkage::Ward),
+        Some(param) => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid linkage '{param}'. Must be 'single', 'complete', 'average' or 'ward'"),
+        )),
+    }
+}
+
+#[post("/index/{index_name}/hierarchy")]
+async fn index_hierarchy(
+    index_name: web::Path<String>,
+    request: web::Json<HierarchyRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let linkage = match parse_linkage(request.linkage.as_deref()) {
+        Ok(linkage) => linkage,
+        Err(resp) => return resp,
+    };
+
+    let count = match &request.k {
+        Some(k) => match parse_cluster_count(k, request.k_min, request.k_max, index.len()) {
+            Ok(count) => Some(count),
+            Err(resp) => return resp,
+        },
+        None => None,
+    };
+
+    let seed = request.seed.unwrap_or(0);
+    let hierarchy = web::block(move || index.hierarchy(linkage, count, seed)).await;
+
+    match hierarchy {
+        Ok(Ok(hierarchy)) => HttpResponse::Ok().json(hierarchy),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Hierarchical clustering failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct LabeledExample {
+    label: String,
+    id: Option<String>,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TrainRequest {
+    index: Option<String>,
+    examples: Vec<LabeledExample>,
+    cost: Option<f64>,
+}
+
+// Examples are either texts, embedded with the model, or ids of entries in
+// `index` whose stored embeddings are reused.
+fn training_embeddings(
+    state: &ServerState,
+    request: &TrainRequest,
+) -> Result<Vec<(String, Vec<f32>)>, HttpResponse> {
+    let index = match &request.index {
+        Some(index_name) => match state.cache.read().unwrap().get(index_name) {
+            Some(index) => Some(Arc::clone(index)),
+            None => {
+                return Err(resp_error(
+                    HttpResponse::NotFound(),
+                    format!("{index_name} not found"),
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let mut texts: Vec<&str> = vec![];
+    let mut ids: Vec<&str> = vec![];
+    for (n, example) in request.examples.iter().enumerate() {
+        match (&example.text, &example.id, &index) {
+            (Some(text), None, _) => texts.push(text),
+            (None, Some(id), Some(_)) => ids.push(id),
+            (None, Some(_), None) => {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("Example {n} has an id but no index was given"),
+                ))
+            }
+            _ => {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("Example {n} must have exactly one of text or id"),
+                ))
+            }
+        }
+    }
+
+    let mut text_embeddings = if texts.is_empty() {
+        vec![]
+    } else {
+        let model = state.model.lock().unwrap();
+        let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+        embedding_cache
+            .compute_normalized_embeddings(&model, &texts)
+            .map_err(|error| {
+                resp_error(
+                    HttpResponse::InternalServerError(),
+                    format!("Could not compute embeddings: {error}"),
+                )
+            })?
+    }
+    .into_iter();
+
+    let mut id_embeddings = match &index {
+        Some(index) => index
+            .embeddings_by_id(&ids)
+            .map_err(|error| resp_error(HttpResponse::BadRequest(), error))?,
+        None => vec![],
+    }
+    .into_iter();
+
+    Ok(request
+        .examples
+        .iter()
+        .map(|example| {
+            let embedding = match example.text {
+                Some(_) => text_embeddings.next(),
+                None => id_embeddings.next(),
+            };
+
+            (example.label.clone(), embedding.unwrap())
+        })
+        .collect())
+}
+
+#[post("/classifiers/{classifier_name}")]
+async fn classifier_train(
+    classifier_name: web::Path<String>,
+    request: web::Json<TrainRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let examples = match training_embeddings(&state, &request) {
+        Ok(examples) => examples,
+        Err(resp) => return resp,
+    };
+
+    state
+        .classifiers
+        .train(&classifier_name, &examples, request.cost)
+        .map_or_else(
+            |error| resp_error(HttpResponse::BadRequest(), error),
+            |info| HttpResponse::Ok().json(info),
+        )
+}
+
+#[get("/classifiers/{classifier_name}")]
+async fn classifier_read(
+    classifier_name: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    match state.classifiers.get(&classifier_name) {
+        Some(info) => HttpResponse::Ok().json(info),
+        None => resp_error(
+            HttpResponse::NotFound(),
+            format!("Classifier {classifier_name} not found"),
+        ),
+    }
+}
+
+#[delete("/classifiers/{classifier_name}")]
+async fn classifier_delete(
+    classifier_name: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    match state.classifiers.delete(&classifier_name) {
+        Some(info) => HttpResponse::Ok().json(info),
+        None => resp_error(
+            HttpResponse::NotFound(),
+            format!("Classifier {classifier_name} not found"),
+        ),
+    }
+}
+
+#[post("/classifiers/{classifier_name}/classify")]
+async fn classifier_classify(
+    classifier_name: web::Path<String>,
+    texts: web::Json<Vec<String>>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    if state.classifiers.get(&classifier_name).is_none() {
+        return resp_error(
+            HttpResponse::NotFound(),
+            format!("Classifier {classifier_name} not found"),
+        );
+    }
+
+    let strs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+    let embeddings = {
+        let model = state.model.lock().unwrap();
+        let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+        match embedding_cache.compute_normalized_embeddings(&model, &strs) {
+            Ok(embeddings) => embeddings,
+            Err(error) => {
+                return resp_error(
+                    HttpResponse::InternalServerError(),
+                    format!("Could not compute embeddings: {error}"),
+                )
+            }
+        }
+    };
+
+    state
+        .classifiers
+        .classify(&classifier_name, &embeddings)
+        .map_or_else(
+            |error| resp_error(HttpResponse::InternalServerError(), error),
+            |classifications| HttpResponse::Ok().json(classifications),
+        )
+}
+
+#[derive(Deserialize)]
+struct CandidateLabel {
+    label: String,
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct ZeroShotRequest {
+    texts: Vec<String>,
+    labels: Vec<CandidateLabel>,
+    temperature: Option<f32>,
+}
+
+const DEFAULT_ZERO_SHOT_TEMPERATURE: f32 = 0.05;
+
+#[post("/classify/zero-shot")]
+async fn classify_zero_shot(
+    request: web::Json<ZeroShotRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    if request.labels.len() < 2 {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            String::from("At least 2 candidate labels are required"),
+        );
+    }
+
+    let temperature = request.temperature.unwrap_or(DEFAULT_ZERO_SHOT_TEMPERATURE);
+    if temperature <= 0.0 {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid temperature {temperature}. Must be greater than 0"),
+        );
+    }
+
+    let labels: Vec<String> = request.labels.iter().map(|l| l.label.clone()).collect();
+    let descriptions: Vec<&str> = request
+        .labels
+        .iter()
+        .map(|l| l.description.as_str())
+        .collect();
+    let texts: Vec<&str> = request.texts.iter().map(|s| s.as_str()).collect();
+
+    let model = state.model.lock().unwrap();
+    let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+    embedding_cache
+        .compute_normalized_embeddings(&model, &descriptions)
+        .and_then(|label_embeddings| {
+            embedding_cache
+                .compute_normalized_embeddings(&model, &texts)
+                .map(|text_embeddings| (label_embeddings, text_embeddings))
+        })
+        .map_or_else(
+            |error| {
+                resp_error(
+                    HttpResponse::InternalServerError(),
+                    format!("Could not compute embeddings: {error}"),
+                )
+            },
+            |(label_embeddings, text_embeddings)| {
+                HttpResponse::Ok().json(zero_shot(
+                    &labels,
+                    &label_embeddings,
+                    &text_embeddings,
+                    temperature,
+                ))
+            },
+        )
+}
+
+#[derive(Deserialize)]
+struct DuplicatesParams {
+    threshold: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RespDuplicates {
+    index: String,
+    threshold: f32,
+    groups: Vec<DuplicateGroup>,
+}
+
+const DEFAULT_DUPLICATE_THRESHOLD: &str = "0.95";
+fn parse_threshold(param: Option<String>, default: &str) -> Result<f32, HttpResponse> {
+    let threshold = param
+        .unwrap_or(default.to_string())
+        .parse::<f32>()
+        .map_err(|err| {
+            resp_error(
+                HttpResponse::BadRequest(),
+                format!("Could not convert threshold query param: {err}"),
+            )
+        })?;
+
+    if threshold > 0.0 && threshold <= 1.0 {
+        Ok(threshold)
+    } else {
+        Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid threshold {threshold}. Must be in (0, 1]"),
+        ))
+    }
+}
+
+#[get("/index/{index_name}/duplicates")]
+async fn index_duplicates(
+    index_name: web::Path<String>,
+    params: web::Query<DuplicatesParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let threshold =
+        match parse_threshold(params.into_inner().threshold, DEFAULT_DUPLICATE_THRESHOLD) {
+            Ok(threshold) => threshold,
+            Err(resp) => return resp,
+        };
+
+    match web::block(move || index.duplicates(threshold)).await {
+        Ok(Ok(groups)) => HttpResponse::Ok().json(RespDuplicates {
+            index: index_name,
+            threshold,
+            groups,
+        }),
+        Ok(Err(error)) => resp_error(HttpResponse::InternalServerError(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Duplicate detection failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct AnomalyParams {
+    method: Option<String>,
+    k: Option<String>,
+    clusters: Option<String>,
+    n: Option<String>,
+    seed: Option<String>,
+}
+
+fn parse_usize_param(
+    name: &str,
+    param: Option<String>,
+    default: &str,
+) -> Result<usize, HttpResponse> {
+    param
+        .unwrap_or(default.to_string())
+        .parse::<usize>()
+        .map_err(|err| {
+            resp_error(
+                HttpResponse::BadRequest(),
+                format!("Could not convert {name} query param: {err}"),
+            )
+        })
+}
+
+const DEFAULT_ANOMALY_K: &str = "10";
+const DEFAULT_ANOMALY_CLUSTERS: &str = "8";
+const DEFAULT_ANOMALY_NRESULTS: &str = "10";
+fn parse_anomaly_method(params: &AnomalyParams) -> Result<AnomalyMethod, HttpResponse> {
+    match params.method.as_deref() {
+        Some("knn") | None => Ok(AnomalyMethod::Knn {
+            k: parse_usize_param("k", params.k.clone(), DEFAULT_ANOMALY_K)?,
+        }),
+        Some("centroid") => Ok(AnomalyMethod::Centroid {
+            clusters: parse_usize_param(
+                "clusters",
+                params.clusters.clone(),
+                DEFAULT_ANOMALY_CLUSTERS,
+            )?,
+        }),
+        Some(param) => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid method '{param}'. Must be 'knn' or 'centroid'"),
+        )),
+    }
+}
+
+#[get("/index/{index_name}/anomalies")]
+async fn index_anomalies(
+    index_name: web::Path<String>,
+    params: web::Query<AnomalyParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let params = params.into_inner();
+    let method = match parse_anomaly_method(&params) {
+        Ok(method) => method,
+        Err(resp) => return resp,
+    };
+    let nresults = match parse_usize_param("n", params.n, DEFAULT_ANOMALY_NRESULTS) {
+        Ok(n) => n,
+        Err(resp) => return resp,
+    };
+    let seed = match parse_usize_param("seed", params.seed, "0") {
+        Ok(seed) => seed as u64,
+        Err(resp) => return resp,
+    };
+
+    let kmeans_params = KMeansParams {
+        method: if index.len() > MINIBATCH_THRESHOLD {
+            KMeansMethod::MiniBatch {
+                batch_size: DEFAULT_CLUSTER_BATCH_SIZE,
+            }
+        } else {
+            KMeansMethod::Full
+        },
+        max_iter: DEFAULT_CLUSTER_MAX_ITER,
+        tolerance: CLUSTER_TOLERANCE,
+        seed,
+    };
+
+    let results = web::block(move || index.anomalies(method, &kmeans_params, nresults)).await;
+
+    match results {
+        Ok(Ok(results)) => HttpResponse::Ok().json(results),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Anomaly detection failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct ProjectionParams {
+    dims: Option<String>,
+    method: Option<String>,
+    sample: Option<String>,
+    perplexity: Option<String>,
+    iterations: Option<String>,
+    seed: Option<String>,
+}
+
+const DEFAULT_PROJECTION_DIMS: &str = "2";
+const DEFAULT_PCA_SAMPLE: &str = "10000";
+const MAX_PCA_SAMPLE: usize = 100_000;
+const DEFAULT_TSNE_SAMPLE: &str = "2000";
+const MAX_TSNE_SAMPLE: usize = 5_000;
+const DEFAULT_TSNE_PERPLEXITY: f32 = 30.0;
+const DEFAULT_TSNE_ITERATIONS: &str = "1000";
+const MAX_TSNE_ITERATIONS: usize = 5_000;
+
+struct ProjectionRequest {
+    dims: usize,
+    method: ProjectionMethod,
+    sample: usize,
+    seed: u64,
+}
+
+fn parse_pca_sample(sample: Option<String>) -> Result<usize, HttpResponse> {
+    let sample = parse_usize_param("sample", sample, DEFAULT_PCA_SAMPLE)?;
+    if sample > MAX_PCA_SAMPLE {
+        return Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("PCA supports a sample of at most {MAX_PCA_SAMPLE} entries"),
+        ));
+    }
+
+    Ok(sample)
+}
+
+fn parse_projection(params: ProjectionParams) -> Result<ProjectionRequest, HttpResponse> {
+    let dims = parse_usize_param("dims", params.dims, DEFAULT_PROJECTION_DIMS)?;
+    if dims != 2 && dims != 3 {
+        return Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid dims {dims}. Must be 2 or 3"),
+        ));
+    }
+    let seed = parse_usize_param("seed", params.seed, "0")? as u64;
+
+    match params.method.as_deref() {
+        Some("pca") | None => {
+            let sample = parse_pca_sample(params.sample)?;
+            Ok(ProjectionRequest {
+                dims,
+                method: ProjectionMethod::Pca { seed },
+                sample,
+                seed,
+            })
+        }
+        Some("tsne") => {
+            let sample = parse_usize_param("sample", params.sample, DEFAULT_TSNE_SAMPLE)?;
+            if sample > MAX_TSNE_SAMPLE {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("t-SNE supports a sample of at most {MAX_TSNE_SAMPLE} entries"),
+                ));
+            }
+
+            let perplexity = match params.perplexity {
+                Some(perplexity) => perplexity.parse::<f32>().map_err(|err| {
+                    resp_error(
+                        HttpResponse::BadRequest(),
+                        format!("Could not convert perplexity query param: {err}"),
+                    )
+                })?,
+                None => DEFAULT_TSNE_PERPLEXITY,
+            };
+            if !(perplexity > 0.0 && perplexity.is_finite()) {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("Invalid perplexity {perplexity}. Must be greater than 0"),
+                ));
+            }
+
+            let iterations =
+                parse_usize_param("iterations", params.iterations, DEFAULT_TSNE_ITERATIONS)?;
+            if iterations > MAX_TSNE_ITERATIONS {
+                return Err(resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("t-SNE supports at most {MAX_TSNE_ITERATIONS} iterations"),
+                ));
+            }
+
+            Ok(ProjectionRequest {
+                dims,
+                method: ProjectionMethod::Tsne(TsneParams {
+                    perplexity,
+                    iterations,
+                    seed,
+                }),
+                sample,
+                seed,
+            })
+        }
+        Some(param) => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid method '{param}'. Must be 'pca' or 'tsne'"),
+        )),
+    }
+}
+
+#[get("/index/{index_name}/projection")]
+async fn index_projection(
+    index_name: web::Path<String>,
+    params: web::Query<ProjectionParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let request = match parse_projection(params.into_inner()) {
+        Ok(request) => request,
+        Err(resp) => return resp,
+    };
+
+    let projection = web::block(move || {
+        index.projection(request.dims, request.method, request.sample, request.seed)
+    })
+    .await;
+
+    match projection {
+        Ok(Ok(projection)) => HttpResponse::Ok().json(projection),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Projection failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReduceParams {
+    dims: Option<String>,
+    sample: Option<String>,
+    seed: Option<String>,
+    dry_run: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct RespReduction {
+    index: String,
+    dims: usize,
+    original_dims: usize,
+    explained_variance: f32,
+    explained_variance_ratio: Vec<f32>,
+    applied: bool,
+}
+
+// The PCA is fitted on a sample while the index stays searchable, and only
+// replacing the stored embeddings by their reduced vectors blocks it. With
+// dry_run, only the explained variance is reported.
+#[post("/index/{index_name}/reduce")]
+async fn index_reduce(
+    index_name: web::Path<String>,
+    params: web::Query<ReduceParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let params = params.into_inner();
+    let dims = match params.dims {
+        Some(dims) => match parse_usize_param("dims", Some(dims), "") {
+            Ok(dims) => dims,
+            Err(resp) => return resp,
+        },
+        None => {
+            return resp_error(
+                HttpResponse::BadRequest(),
+                String::from("Missing dims param"),
+            )
+        }
+    };
+    let sample = match parse_pca_sample(params.sample) {
+        Ok(sample) => sample,
+        Err(resp) => return resp,
+    };
+    let seed = match parse_usize_param("seed", params.seed, "0") {
+        Ok(seed) => seed as u64,
+        Err(resp) => return resp,
+    };
+    let dry_run = params.dry_run.unwrap_or(false);
+
+    if !dry_run && index.is_read_only() {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} is read-only"),
+        );
+    }
+
+    // Fitting and reducing every embedding are both slow, so they run on the
+    // blocking thread pool.
+    let resp = web::block(move || {
+        let reduction = index.fit_reduction(dims, sample, seed)?;
+        let resp = RespReduction {
+            index: index_name,
+            dims: reduction.dimensions(),
+            original_dims: reduction.input_dimensions(),
+            explained_variance: reduction.explained_variance(),
+            explained_variance_ratio: reduction.explained_variance_ratio.clone(),
+            applied: !dry_run,
+        };
+
+        if !dry_run {
+            index.apply_reduction(reduction)?;
+        }
+
+        Ok::<RespReduction, String>(resp)
+    })
+    .await;
+
+    match resp {
+        Ok(Ok(resp)) => HttpResponse::Ok().json(resp),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Reduction failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct RecallParams {
+    k: Option<String>,
+    queries: Option<String>,
+    method: Option<String>,
+    oversample: Option<String>,
+    dims: Option<String>,
+    sample: Option<String>,
+    seed: Option<String>,
+}
+
+const DEFAULT_RECALL_K: &str = "10";
+const DEFAULT_RECALL_QUERIES: &str = "100";
+
+#[get("/index/{index_name}/recall")]
+async fn index_recall(
+    index_name: web::Path<String>,
+    params: web::Query<RecallParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let params = params.into_inner();
+    let k = match parse_usize_param("k", params.k, DEFAULT_RECALL_K) {
+        Ok(k) => k,
+        Err(resp) => return resp,
+    };
+    let queries = match parse_usize_param("queries", params.queries, DEFAULT_RECALL_QUERIES) {
+        Ok(queries) => queries,
+        Err(resp) => return resp,
+    };
+    let seed = match parse_usize_param("seed", params.seed, "0") {
+        Ok(seed) => seed as u64,
+        Err(resp) => return resp,
+    };
+
+    let oversample = match params.oversample {
+        Some(oversample) => match oversample.parse::<f32>() {
+            Ok(oversample) => oversample,
+            Err(err) => {
+                return resp_error(
+                    HttpResponse::BadRequest(),
+                    format!("Could not convert oversample query param: {err}"),
+                )
+            }
+        },
+        None => index
+            .settings()
+            .quantization
+            .as_ref()
+            .map_or(DEFAULT_OVERSAMPLE, |quantization| quantization.oversample),
+    };
+    let method = match params.method.as_deref() {
+        Some("binary") | None => ApproximateMethod::Binary { oversample },
+        Some("pca") => {
+            let dims = match params.dims {
+                Some(dims) => match parse_usize_param("dims", Some(dims), "") {
+                    Ok(dims) => dims,
+                    Err(resp) => return resp,
+                },
+                None => {
+                    return resp_error(
+                        HttpResponse::BadRequest(),
+                        String::from("Missing dims param"),
+                    )
+                }
+            };
+            let sample = match parse_usize_param("sample", params.sample, DEFAULT_PCA_SAMPLE) {
+                Ok(sample) => sample,
+                Err(resp) => return resp,
+            };
+            ApproximateMethod::Pca { dims, sample }
+        }
+        Some(param) => {
+            return resp_error(
+                HttpResponse::BadRequest(),
+                format!("Invalid method '{param}'. Must be 'binary' or 'pca'"),
+            )
+        }
+    };
+
+    // A pca evaluation fits and applies a reduction, so like every
+    // evaluation it runs on the blocking thread pool.
+    let report = web::block(move || index.evaluate_recall(method, k, queries, seed)).await;
+
+    match report {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Recall evaluation failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct LabeledQuery {
+    q: String,
+    relevant: Vec<Judgement>,
+}
+
+#[derive(Deserialize)]
+struct RelevanceRequest {
+    queries: Vec<LabeledQuery>,
+    k: Option<usize>,
+    methods: Option<Vec<String>>,
+    oversample: Option<f32>,
+    collapse: Option<bool>,
+}
+
+const DEFAULT_RELEVANCE_K: usize = 10;
+const MAX_RELEVANCE_K: usize = 100;
+const MAX_RELEVANCE_QUERIES: usize = 1_000;
+
+fn parse_ranking_method(
+    method: &str,
+    quantization: Option<&QuantizationConfig>,
+    oversample: Option<f32>,
+) -> Result<RankingMethod, HttpResponse> {
+    match method {
+        "cosine" => Ok(RankingMethod::Cosine),
+        "svm" => Ok(RankingMethod::ExemplarSVM),
+        "binary" => match quantization {
+            Some(quantization) => Ok(RankingMethod::Binary {
+                oversample: oversample.unwrap_or(quantization.oversample),
+            }),
+            None => Err(resp_error(
+                HttpResponse::BadRequest(),
+                String::from("Method 'binary' requires a quantized index"),
+            )),
+        },
+        _ => Err(resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid method '{method}'. Must be 'cosine', 'svm' or 'binary'"),
+        )),
+    }
+}
+
+#[post("/index/{index_name}/relevance")]
+async fn index_relevance(
+    index_name: web::Path<String>,
+    request: web::Json<RelevanceRequest>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let request = request.into_inner();
+    let k = request.k.unwrap_or(DEFAULT_RELEVANCE_K);
+    if k == 0 || k > MAX_RELEVANCE_K {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("Invalid k {k}. Must be between 1 and {MAX_RELEVANCE_K}"),
+        );
+    }
+    if request.queries.is_empty() || request.queries.len() > MAX_RELEVANCE_QUERIES {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("Between 1 and {MAX_RELEVANCE_QUERIES} labeled queries are required"),
+        );
+    }
+    if let Some(query) = request.queries.iter().find(|query| {
+        !query
+            .relevant
+            .iter()
+            .any(|judgement| judgement.grade() > 0.0)
+    }) {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("Query '{}' has no relevant ids", query.q),
+        );
+    }
+
+    let keys: Vec<&str> = request
+        .queries
+        .iter()
+        .flat_map(|query| query.relevant.iter().map(|judgement| judgement.id()))
+        .collect();
+    let missing = index.missing_keys(&keys);
+    if !missing.is_empty() {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("Unknown relevant ids: {}", missing.join(", ")),
+        );
+    }
+
+    // Binary search is evaluated by default when the index is quantized.
+    let settings = index.settings();
+    let quantization = settings.quantization.as_ref();
+    let method_names = request.methods.clone().unwrap_or_else(|| {
+        let mut methods = vec![String::from("cosine"), String::from("svm")];
+        if quantization.is_some() {
+            methods.push(String::from("binary"));
+        }
+        methods
+    });
+    let mut methods = Vec::with_capacity(method_names.len());
+    for name in &method_names {
+        match parse_ranking_method(name, quantization, request.oversample) {
+            Ok(method) => methods.push(method),
+            Err(resp) => return resp,
+        }
+    }
+
+    let pipeline = index.pipeline();
+    let texts: Vec<String> = request
+        .queries
+        .iter()
+        .map(|query| pipeline.apply(&query.q))
+        .collect();
+    let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+    let embeddings = {
+        let model = state.model.lock().unwrap();
+        let mut embedding_cache = state.embedding_cache.lock().unwrap();
+
+        embedding_cache.compute_normalized_embeddings(&model, &texts)
+    };
+    let embeddings = match embeddings {
+        Ok(embeddings) => embeddings,
+        Err(error) => {
+            return resp_error(
+                HttpResponse::InternalServerError(),
+                format!("Could not compute embeddings: {error}"),
+            )
+        }
+    };
+
+    let judgements: Vec<Vec<Judgement>> = request
+        .queries
+        .into_iter()
+        .map(|query| query.relevant)
+        .collect();
+
+    let collapse = request.collapse.unwrap_or(false);
+
+    // Every query is a full scan per method, and an SVM training for `svm`,
+    // so the evaluation runs on the blocking thread pool.
+    let report = web::block(move || {
+        evaluation::evaluate_relevance(&index, &embeddings, &judgements, &methods, k, collapse)
+    })
+    .await;
+
+    match report {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(error)) => resp_error(HttpResponse::BadRequest(), error),
+        Err(error) => resp_error(
+            HttpResponse::InternalServerError(),
+            format!("Relevance evaluation failed: {error}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    format: Option<String>,
+}
+
+const EXPORT_CHUNK_SIZE: usize = 1024;
+
+// The export is encoded one slice of the index at a time as the client reads
+// it. The number of rows is fixed when the export starts, so entries appended
+// meanwhile are not included.
+#[get("/index/{index_name}/export")]
+async fn index_export(
+    index_name: web::Path<String>,
+    params: web::Query<ExportParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let format = match ExportFormat::parse(params.format.as_deref().unwrap_or("jsonl")) {
+        Ok(format) => format,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
+    // Rows appended during the export are left out. Any other change ends
+    // the stream with an error, as rows already sent may be stale.
+    let (generation, rows, dimensions) = index.shape();
+    let mut exporter = Exporter::new(format, rows, dimensions);
+    let header = match exporter.header() {
+        Ok(header) => header,
+        Err(error) => return resp_error(HttpResponse::InternalServerError(), error),
+    };
+
+    let body = futures_util::stream::unfold(Some((exporter, 0)), move |state| {
+        let index = Arc::clone(&index);
+        async move {
+            let (mut exporter, start) = state?;
+            if start >= rows {
+                return Some((exporter.footer(), None));
+            }
+
+            let end = (start + EXPORT_CHUNK_SIZE).min(rows);
+            match index.slice_at(generation, start, end) {
+                Ok((texts, embeddings)) => {
+                    Some((exporter.chunk(&texts, &embeddings), Some((exporter, end))))
+                }
+                Err(error) => Some((Err(error), None)),
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{index_name}.{}\"",
+                format.extension()
+            ),
+        ))
+        .streaming(
+            futures_util::stream::once(async { Ok(header) })
+                .chain(body)
+                .map(|bytes| bytes.map(web::Bytes::from)),
+        )
+}
+
+#[derive(Deserialize)]
+struct ImportParams {
+    format: Option<String>,
+}
+
+// Imports are parsed from a body buffered in memory, so it is capped at
+// `limit` bytes, set by the MAX_IMPORT_SIZE environment variable.
+async fn read_payload(mut payload: web::Payload, limit: usize) -> Result<Vec<u8>, String> {
+    let mut body: Vec<u8> = vec![];
+    while let Some(bytes) = payload.next().await {
+        let bytes = bytes.map_err(|err| format!("Error reading payload: {err}"))?;
+        if body.len() + bytes.len() > limit {
+            return Err(payload_too_large(limit));
+        }
+        body.extend_from_slice(&bytes);
+    }
+
+    Ok(body)
+}
+
+fn payload_too_large(limit: usize) -> String {
+    format!("Payload exceeds the maximum import size of {limit} bytes")
+}
+
+// An npy matrix has no ids or texts, so it is uploaded as a multipart form
+// with the matrix in an `embeddings` part and the entries for its rows, in the
+// same order, in either an `ids` or a `texts` part.
+async fn read_npy_import(
+    req: &HttpRequest,
+    payload: web::Payload,
+    limit: usize,
+) -> Result<(Vec<TextBody>, Vec<Vec<f32>>), String> {
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut text_bodies: Option<Vec<TextBody>> = None;
+    let mut embeddings: Option<Vec<Vec<f32>>> = None;
+    let mut received: usize = 0;
+
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(|err| format!("Invalid multipart body: {err}"))?;
+        let name = field.name().map(String::from);
+
+        let mut bytes: Vec<u8> = vec![];
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|err| format!("Invalid multipart body: {err}"))?;
+            received += chunk.len();
+            if received > limit {
+                return Err(payload_too_large(limit));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        match name.as_deref() {
+            Some("embeddings") => embeddings = Some(parse_npy(&bytes)?),
+            Some("ids") => text_bodies = Some(parse_ids(&bytes)?),
+            Some("texts") => text_bodies = Some(parse_texts(&bytes)?),
+            _ => (),
+        }
+    }
+
+    match (text_bodies, embeddings) {
+        (Some(text_bodies), Some(embeddings)) => Ok((text_bodies, embeddings)),
+        _ => Err(String::from(
+            "npy imports require an 'embeddings' part and either an 'ids' or a 'texts' part",
+        )),
+    }
+}
+
+// Builds a new index from embeddings computed elsewhere, without running the
+// model. Queries are still embedded by the model, so imported embeddings must
+// come from the same model to be comparable.
+#[post("/index/{index_name}/import")]
+async fn index_import(
+    index_name: web::Path<String>,
+    params: web::Query<ImportParams>,
+    req: HttpRequest,
+    payload: web::Payload,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let format = match ImportFormat::parse(params.format.as_deref().unwrap_or("jsonl")) {
+        Ok(format) => format,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
+    let index_name = index_name.to_string();
+    let already_exists = || {
+        resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} already exists"),
+        )
+    };
+    if state.cache.read().unwrap().contains_key(&index_name) {
+        return already_exists();
+    }
+
+    let imported = match format {
+        ImportFormat::Jsonl => read_payload(payload, state.max_import_size)
+            .await
+            .and_then(|body| parse_jsonl(&body)),
+        ImportFormat::Arrow => read_payload(payload, state.max_import_size)
+            .await
+            .and_then(|body| parse_arrow(&body)),
+        ImportFormat::Npy => read_npy_import(&req, payload, state.max_import_size).await,
+    };
+
+    let index = match imported.and_then(|(text_bodies, embeddings)| {
+        normalize_embeddings(&text_bodies, embeddings, state.dimensions)
+            .and_then(|embeddings| GuardedIndex::new(text_bodies, embeddings))
+    }) {
+        Ok(index) => index,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
+    let mut cache = state.cache.write().unwrap();
+    if cache.contains_key(&index_name) {
+        return already_exists();
+    }
+
+    let size = index.len();
+    cache.insert(index_name.clone(), Arc::new(index));
+
+    ok_resp_index(index_name, size)
+}
+
+#[derive(Deserialize)]
+struct SnapshotParams {
+    snapshot: Option<String>,
+}
+
+#[post("/index/{index_name}/snapshot")]
+async fn index_snapshot(
+    index_name: web::Path<String>,
+    params: web::Query<SnapshotParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    // A single slice is read under one lock, so the snapshot is consistent even
+    // while entries are being appended.
+    let (texts, embeddings) = index.slice(0, usize::MAX);
+
+    state
+        .snapshots
+        .write(
+            params.snapshot.as_deref(),
+            &index_name,
+            index.settings(),
+            index.reduction().as_ref(),
+            &texts,
+            &embeddings,
+        )
+        .map_or_else(
+            |error| resp_error(HttpResponse::BadRequest(), error),
+            |info| HttpResponse::Ok().json(info),
+        )
+}
+
+#[post("/index/{index_name}/restore")]
+async fn index_restore(
+    index_name: web::Path<String>,
+    params: web::Query<SnapshotParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let snapshot_name = match &params.snapshot {
+        Some(snapshot_name) => snapshot_name,
+        None => {
+            return resp_error(
+                HttpResponse::BadRequest(),
+                String::from("Missing snapshot param"),
+            )
+        }
+    };
+
+    if state.cache.read().unwrap().contains_key(&index_name) {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} already exists"),
+        );
+    }
+
+    let snapshot = match state.snapshots.read(snapshot_name) {
+        Ok(snapshot) => snapshot,
+        Err(error) => return resp_error(HttpResponse::NotFound(), error),
+    };
+    log::info!(
+        "Restoring snapshot {} of {} as {index_name}",
+        snapshot.info.name,
+        snapshot.info.index
+    );
+    let settings = snapshot.settings.clone();
+    let index = match GuardedIndex::new(snapshot.texts, snapshot.embeddings)
+        .and_then(|index| index.with_settings(snapshot.settings))
+        .and_then(|index| index.with_reduction(snapshot.reduction))
+    {
+        Ok(index) => index,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
+    let mut cache = state.cache.write().unwrap();
+    if cache.contains_key(&index_name) {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} already exists"),
+        );
+    }
+
+    let size = index.len();
+    cache.insert(index_name.clone(), Arc::new(index));
+
+    ok_resp_index_with_settings(index_name, size, settings, false)
+}
+
+#[derive(Deserialize)]
+struct MappedParams {
+    file: Option<String>,
+}
+
+#[post("/index/{index_name}/mapped")]
+async fn index_write_mapped(
+    index_name: web::Path<String>,
+    params: web::Query<MappedParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let index = match state.cache.read().unwrap().get(&index_name) {
+        Some(index) => Arc::clone(index),
+        None => return resp_error(HttpResponse::NotFound(), format!("{index_name} not found")),
+    };
+
+    let (texts, embeddings) = index.slice(0, usize::MAX);
+    let file = params.file.as_deref().unwrap_or(&index_name);
+
+    state
+        .mapped
+        .write(
+            file,
+            index.settings(),
+            index.reduction().as_ref(),
+            &texts,
+            &embeddings,
+        )
+        .map_or_else(
+            |error| resp_error(HttpResponse::BadRequest(), error),
+            |_| ok_resp_index(index_name.clone(), texts.len()),
+        )
+}
+
+// Mounted indices only load their entries into memory. Their embeddings stay
+// on disk and are read through the page cache, so they cannot be appended to.
+#[post("/index/{index_name}/mount")]
+async fn index_mount(
+    index_name: web::Path<String>,
+    params: web::Query<MappedParams>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let index_name = index_name.to_string();
+    let file = params.file.as_deref().unwrap_or(&index_name);
+
+    if state.cache.read().unwrap().contains_key(&index_name) {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} already exists"),
+        );
+    }
+
+    let (settings, reduction, texts, embeddings) = match state.mapped.read(file) {
+        Ok(mapped) => mapped,
+        Err(error) => return resp_error(HttpResponse::NotFound(), error),
+    };
+    let index = match GuardedIndex::mapped(texts, embeddings)
+        .and_then(|index| index.with_settings(settings.clone()))
+        .and_then(|index| index.with_reduction(reduction))
+    {
+        Ok(index) => index,
+        Err(error) => return resp_error(HttpResponse::BadRequest(), error),
+    };
+
+    let mut cache = state.cache.write().unwrap();
+    if cache.contains_key(&index_name) {
+        return resp_error(
+            HttpResponse::BadRequest(),
+            format!("{index_name} already exists"),
+        );
+    }
+
+    let size = index.len();
+    cache.insert(index_name.clone(), Arc::new(index));
+
+    ok_resp_index_with_settings(index_name, size, settings, true)
+}
+
+#[get("/cache")]
+async fn cache_stats(state: web::Data<ServerState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.embedding_cache.lock().unwrap().stats())
+}
+
+#[get("/")]
+async fn root() -> Result<NamedFile> {
+    Ok(NamedFile::open("root.html")?)
+}
+struct ServerState {
+    model: Mutex<SentenceTransformer>,
+    tokenizer: Mutex<SentenceTokenizer>,
+    max_sequence_length: usize,
+    dimensions: usize,
+    max_import_size: usize,
+    embedding_cache: Mutex<EmbeddingCache>,
+    cache: Arc<RwLock<HashMap<String, Arc<GuardedIndex>>>>,
+    jobs: JobRegistry,
+    classifiers: ClassifierRegistry,
+    snapshots: SnapshotStore,
+    mapped: MappedStore,
+}
+
+const DEFAULT_MODEL_PATH: &str = "models/distiluse-base-multilingual-cased-converted";
+const DEFAULT_EMBEDDING_CACHE_SIZE: usize = 10_000;
+const DEFAULT_MAX_IMPORT_SIZE: usize = 1024 * 1024 * 1024;
+const DEFAULT_CLASSIFIER_DIR: &str = "classifiers";
+const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+const DEFAULT_MAPPED_DIR: &str = "mapped";
+const DEFAULT_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_PORT: u16 = 8000;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let model_path = env::var("MODEL_PATH").unwrap_or(String::from(DEFAULT_MODEL_PATH));
     let model = match load_model(&model_path) {
         Ok(m) => m,
         Err(e) => panic!("Failed to load sentence_transformer: {e}"),
     };
+    let tokenizer = match load_tokenizer(&model_path) {
+        Ok(t) => t,
+        Err(e) => panic!("Failed to load tokenizer: {e}"),
+    };
+    let max_sequence_length = match load_max_sequence_length(&model_path) {
+        Ok(length) => length,
+        Err(e) => panic!("Failed to load model config: {e}"),
+    };
+    let dimensions = match embedding_dimensions(&model) {
+        Ok(dimensions) => dimensions,
+        Err(e) => panic!("Failed to embed with sentence_transformer: {e}"),
+    };
+
+    let embedding_cache_size: usize = env::var("EMBEDDING_CACHE_SIZE")
+        .map(|size_str| {
+            size_str
+                .parse::<usize>()
+                .unwrap_or(DEFAULT_EMBEDDING_CACHE_SIZE)
+        })
+        .unwrap_or(DEFAULT_EMBEDDING_CACHE_SIZE);
+
+    let max_import_size: usize = env::var("MAX_IMPORT_SIZE")
+        .map(|size_str| size_str.parse::<usize>().unwrap_or(DEFAULT_MAX_IMPORT_SIZE))
+        .unwrap_or(DEFAULT_MAX_IMPORT_SIZE);
+
+    let classifier_dir = env::var("CLASSIFIER_DIR").unwrap_or(String::from(DEFAULT_CLASSIFIER_DIR));
+    let classifiers = match ClassifierRegistry::open(&classifier_dir) {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to open classifiers: {e}"),
+    };
+
+    let snapshot_dir = env::var("SNAPSHOT_DIR").unwrap_or(String::from(DEFAULT_SNAPSHOT_DIR));
+    let snapshots = match SnapshotStore::open(&snapshot_dir) {
+        Ok(s) => s,
+        Err(e) => panic!("Failed to open snapshots: {e}"),
+    };
+
+    let mapped_dir = env::var("MAPPED_DIR").unwrap_or(String::from(DEFAULT_MAPPED_DIR));
+    let mapped = match MappedStore::open(&mapped_dir) {
+        Ok(m) => m,
+        Err(e) => panic!("Failed to open mapped indices: {e}"),
+    };
 
     let state = web::Data::new(ServerState {
         model: Mutex::new(model),
+        tokenizer: Mutex::new(tokenizer),
+        max_sequence_length,
+        dimensions,
+        max_import_size,
+        embedding_cache: Mutex::new(EmbeddingCache::new(&model_path, embedding_cache_size)),
         cache: Arc::new(RwLock::new(HashMap::new())),
+        jobs: JobRegistry::new(),
+        classifiers,
+        snapshots,
+        mapped,
     });
 
     let address = env::var("SCOUT_ADDRESS").unwrap_or(String::from(DEFAULT_ADDRESS));
@@ -284,9 +2703,33 @@ async fn main() -> std::io::Result<()> {
             .service(index_create)
             .service(index_read)
             .service(index_update)
+            .service(index_stream)
             .service(index_delete)
+            .service(job_read)
+            .service(job_cancel)
             .service(query_index)
+            .service(index_cluster)
+            .service(index_hierarchy)
+            .service(index_duplicates)
+            .service(index_anomalies)
+            .service(index_projection)
+            .service(index_reduce)
+            .service(index_recall)
+            .service(index_relevance)
+            .service(index_export)
+            .service(index_import)
+            .service(index_snapshot)
+            .service(index_restore)
+            .service(index_write_mapped)
+            .service(index_mount)
+            .service(classifier_train)
+            .service(classifier_read)
+            .service(classifier_delete)
+            .service(classifier_classify)
+            .service(classify_zero_shot)
             .service(compute_weights)
+            .service(similarity)
+            .service(cache_stats)
             .wrap(Logger::default())
             .wrap(cors)
             .wrap(Compress::default())
@@ -0,0 +1,338 @@
+use crate::export::npy_header;
+use crate::import::{parse_npy_header, parse_texts};
+use crate::names::valid_name;
+use crate::projection::Reduction;
+use crate::vector_index::{IndexSettings, TextBody};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const SETTINGS: &str = "settings.json";
+const TEXTS: &str = "texts.jsonl";
+const EMBEDDINGS: &str = "embeddings.npy";
+const REDUCTION: &str = "reduction.json";
+
+// A read-only float32 matrix memory-mapped from an .npy file. Rows are read
+// straight from the page cache, which is shared by every process mapping the
+// same file, and opening a file only reads its header.
+pub struct MappedMatrix {
+    mmap: Mmap,
+    offset: usize,
+    rows: usize,
+    dim: usize,
+}
+
+impl MappedMatrix {
+    pub fn open(path: &Path) -> Result<MappedMatrix, String> {
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        // Safety: the file must not be modified while it is mapped. Mapped
+        // indices are read-only, and files are replaced by renaming rather
+        // than rewritten in place.
+        let mmap = unsafe { Mmap::map(&file) }
+            .map_err(|err| format!("Could not map {}: {err}", path.display()))?;
+
+        let header = parse_npy_header(&mmap)?;
+        if header.descr != "<f4" || cfg!(target_endian = "big") {
+            return Err(format!(
+                "Mapped embeddings must be little-endian float32, got '{}'",
+                header.descr
+            ));
+        }
+        let len = header
+            .data_len(std::mem::size_of::<f32>())
+            .and_then(|len| {
+                len.checked_add(header.data_start)
+                    .ok_or_else(|| String::from("npy file is too large"))
+            })?;
+        if mmap.len() != len {
+            return Err(format!(
+                "{} does not hold a ({}, {}) matrix",
+                path.display(),
+                header.rows,
+                header.cols
+            ));
+        }
+        if mmap[header.data_start..]
+            .as_ptr()
+            .align_offset(std::mem::align_of::<f32>())
+            != 0
+        {
+            return Err(format!("{} is not aligned for float32", path.display()));
+        }
+
+        Ok(MappedMatrix {
+            mmap,
+            offset: header.data_start,
+            rows: header.rows,
+            dim: header.cols,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dim
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        // Safety: `open` checked that the data is aligned, little-endian and
+        // holds exactly rows * dim floats, and every bit pattern is a valid
+        // f32.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.mmap[self.offset..].as_ptr() as *const f32,
+                self.rows * self.dim,
+            )
+        }
+    }
+
+    pub fn row(&self, row: usize) -> &[f32] {
+        &self.as_slice()[row * self.dim..(row + 1) * self.dim]
+    }
+}
+
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), String> {
+    File::create(path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.into_inner()?.sync_all()
+        })
+        .map_err(|err| format!("Could not write {}: {err}", path.display()))
+}
+
+// Mapped indices are saved to `{dir}/{name}/`, with the index settings, its
+// entries as JSONL and its embeddings as an npy matrix, like an unpacked
+// snapshot, with the PCA of indices that were reduced. Entries are loaded into
+// memory and embeddings are mapped.
+pub struct MappedStore {
+    dir: PathBuf,
+}
+
+impl MappedStore {
+    pub fn open(dir: &str) -> Result<MappedStore, String> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Could not create mapped index directory: {err}"))?;
+
+        Ok(MappedStore { dir })
+    }
+
+    // Files are written to a temporary directory which then replaces any
+    // previous version, so processes that already mapped the previous files
+    // keep reading them until they reload.
+    pub fn write(
+        &self,
+        name: &str,
+        settings: &IndexSettings,
+        reduction: Option<&Reduction>,
+        texts: &[TextBody],
+        embeddings: &[Vec<f32>],
+    ) -> Result<(), String> {
+        valid_name("mapped index", name)?;
+
+        let dim = embeddings.first().map_or(0, |embedding| embedding.len());
+        let tmp_dir = self.dir.join(format!(".{name}.tmp"));
+        let _ = fs::remove_dir_all(&tmp_dir);
+
+        let result = fs::create_dir_all(&tmp_dir)
+            .map_err(|err| format!("Could not create {}: {err}", tmp_dir.display()))
+            .and_then(|_| {
+                write_file(&tmp_dir.join(SETTINGS), |writer| {
+                    serde_json::to_writer(writer, settings).map_err(std::io::Error::from)
+                })
+            })
+            .and_then(|_| {
+                write_file(&tmp_dir.join(TEXTS), |writer| {
+                    for text_body in texts {
+                        serde_json::to_writer(&mut *writer, text_body)?;
+                        writer.write_all(b"\n")?;
+                    }
+                    Ok(())
+                })
+            })
+            .and_then(|_| {
+                write_file(&tmp_dir.join(EMBEDDINGS), |writer| {
+                    writer.write_all(&npy_header(embeddings.len(), dim))?;
+                    for embedding in embeddings {
+                        for x in embedding {
+                            writer.write_all(&x.to_le_bytes())?;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .and_then(|_| match reduction {
+                Some(reduction) => write_file(&tmp_dir.join(REDUCTION), |writer| {
+                    serde_json::to_writer(writer, reduction).map_err(std::io::Error::from)
+                }),
+                None => Ok(()),
+            })
+            .and_then(|_| {
+                let path = self.dir.join(name);
+                let old_dir = self.dir.join(format!(".{name}.old"));
+                let _ = fs::remove_dir_all(&old_dir);
+                if path.exists() {
+                    fs::rename(&path, &old_dir)
+                        .map_err(|err| format!("Could not replace {name}: {err}"))?;
+                }
+
+                fs::rename(&tmp_dir, &path)
+                    .map_err(|err| format!("Could not save {name}: {err}"))?;
+                let _ = fs::remove_dir_all(&old_dir);
+
+                Ok(())
+            });
+
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&tmp_dir);
+        }
+
+        result
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn read(
+        &self,
+        name: &str,
+    ) -> Result<
+        (
+            IndexSettings,
+            Option<Reduction>,
+            Vec<TextBody>,
+            MappedMatrix,
+        ),
+        String,
+    > {
+        valid_name("mapped index", name)?;
+
+        let path = self.dir.join(name);
+        if !path.is_dir() {
+            return Err(format!("Mapped index {name} not found"));
+        }
+
+        let settings = fs::read(path.join(SETTINGS))
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_slice(&json).map_err(|err| err.to_string()))
+            .map_err(|err| format!("Could not read settings of {name}: {err}"))?;
+        let texts = fs::read(path.join(TEXTS))
+            .map_err(|err| err.to_string())
+            .and_then(|jsonl| parse_texts(&jsonl))
+            .map_err(|err| format!("Could not read entries of {name}: {err}"))?;
+        let embeddings = MappedMatrix::open(&path.join(EMBEDDINGS))?;
+        let reduction = match fs::read(path.join(REDUCTION)) {
+            Ok(json) => Some(
+                serde_json::from_slice(&json)
+                    .map_err(|err| format!("Could not read reduction of {name}: {err}"))?,
+            ),
+            Err(_) => None,
+        };
+
+        if texts.len() != embeddings.len() {
+            return Err(format!(
+                "{name} has {} entries but {} embeddings",
+                texts.len(),
+                embeddings.len()
+            ));
+        }
+
+        Ok((settings, reduction, texts, embeddings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> MappedStore {
+        let dir = std::env::temp_dir().join(format!("scout-mapped-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+
+        MappedStore::open(&dir.to_string_lossy()).expect("Could not open store")
+    }
+
+    #[test]
+    fn test_write_and_map() {
+        let store = temp_store("roundtrip");
+        let texts = vec![
+            TextBody {
+                id: String::from("a"),
+                text: String::from("first"),
+                parent: None,
+            },
+            TextBody {
+                id: String::from("b"),
+                text: String::from("second"),
+                parent: None,
+            },
+        ];
+
+        store
+            .write(
+                "corpus",
+                &IndexSettings::default(),
+                None,
+                &texts,
+                &[vec![1.0, 0.0], vec![0.6, 0.8]],
+            )
+            .expect("Could not write");
+        let (_, reduction, texts, embeddings) = store.read("corpus").expect("Could not map");
+        assert!(reduction.is_none());
+        assert_eq!(texts[1].id, "b");
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings.dimensions(), 2);
+        assert_eq!(embeddings.row(1), &[0.6, 0.8]);
+        assert_eq!(embeddings.as_slice(), &[1.0, 0.0, 0.6, 0.8]);
+
+        // Rewriting replaces the files without disturbing existing mappings.
+        store
+            .write(
+                "corpus",
+                &IndexSettings::default(),
+                None,
+                &texts[..1],
+                &[vec![0.0, 1.0]],
+            )
+            .expect("Could not rewrite");
+        assert_eq!(embeddings.row(1), &[0.6, 0.8]);
+        assert_eq!(store.read("corpus").unwrap().3.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let store = temp_store("invalid");
+
+        assert!(store.read("../escape").is_err());
+        assert_eq!(
+            store.read("missing").err().unwrap(),
+            "Mapped index missing not found"
+        );
+
+        let path = store.dir.join("float64.npy");
+        let mut bytes = npy_header(1, 1);
+        let descr = bytes.windows(3).position(|w| w == b"<f4").unwrap();
+        bytes[descr + 2] = b'8';
+        bytes.extend_from_slice(&1.0_f64.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        assert!(MappedMatrix::open(&path).is_err());
+
+        let path = store.dir.join("overflow.npy");
+        fs::write(&path, npy_header(usize::MAX / 2, 2)).unwrap();
+        assert!(MappedMatrix::open(&path)
+            .err()
+            .unwrap()
+            .contains("too large"));
+    }
+}
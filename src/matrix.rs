@@ -0,0 +1,243 @@
+// Embeddings stored as one row-major matrix, so a scan reads contiguous memory
+// instead of following a pointer per row.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Matrix {
+    data: Vec<f32>,
+    dim: usize,
+}
+
+impl Matrix {
+    pub fn from_rows(rows: Vec<Vec<f32>>) -> Result<Matrix, String> {
+        let mut matrix = Matrix::default();
+        matrix.extend(&rows)?;
+
+        Ok(matrix)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len().checked_div(self.dim).unwrap_or(0)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dim
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn row(&self, row: usize) -> &[f32] {
+        &self.data[row * self.dim..(row + 1) * self.dim]
+    }
+
+    // Checks that rows can be added: the dimension of an empty matrix is set
+    // by its first row.
+    pub fn validate(&self, rows: &[Vec<f32>]) -> Result<(), String> {
+        let dim = if self.is_empty() {
+            rows.first().map_or(0, |row| row.len())
+        } else {
+            self.dim
+        };
+
+        if !rows.is_empty() && dim == 0 {
+            return Err(String::from("Embeddings must not be empty"));
+        }
+
+        match rows.iter().find(|row| row.len() != dim) {
+            Some(row) => Err(format!(
+                "Embedding has dimension {} (expected {dim})",
+                row.len()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn extend(&mut self, rows: &[Vec<f32>]) -> Result<(), String> {
+        self.validate(rows)?;
+
+        if let Some(row) = rows.first() {
+            self.dim = row.len();
+        }
+        self.data.reserve(rows.len() * self.dim);
+        for row in rows {
+            self.data.extend_from_slice(row);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_row(&mut self, row: usize, values: &[f32]) -> Result<(), String> {
+        if values.len() != self.dim {
+            return Err(format!(
+                "Embedding has dimension {} (expected {})",
+                values.len(),
+                self.dim
+            ));
+        }
+
+        self.data[row * self.dim..(row + 1) * self.dim].copy_from_slice(values);
+
+        Ok(())
+    }
+}
+
+// The reference dot product, which sums in order.
+pub fn dot_scalar(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .fold(0.0, |sum, (ae, be)| sum + (ae * be))
+}
+
+// Eight independent sums break the dependency between additions, which lets
+// the compiler vectorize the loop with whatever SIMD the target has, such as
+// NEON on aarch64.
+fn dot_unrolled(a: &[f32], b: &[f32]) -> f32 {
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail = dot_scalar(a_chunks.remainder(), b_chunks.remainder());
+
+    let mut sums = [0.0_f32; 8];
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for lane in 0..8 {
+            sums[lane] += x[lane] * y[lane];
+        }
+    }
+
+    sums.iter().sum::<f32>() + tail
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn dot_avx2(a: &[f32], b: &[f32]) -> f32 {
+    use std::arch::x86_64::*;
+
+    let n = a.len().min(b.len());
+    let (a_ptr, b_ptr) = (a.as_ptr(), b.as_ptr());
+    let mut sums = [_mm256_setzero_ps(); 4];
+
+    let mut i = 0;
+    while i + 32 <= n {
+        for (lane, sum) in sums.iter_mut().enumerate() {
+            let offset = i + lane * 8;
+            *sum = _mm256_fmadd_ps(
+                _mm256_loadu_ps(a_ptr.add(offset)),
+                _mm256_loadu_ps(b_ptr.add(offset)),
+                *sum,
+            );
+        }
+        i += 32;
+    }
+    while i + 8 <= n {
+        sums[0] = _mm256_fmadd_ps(
+            _mm256_loadu_ps(a_ptr.add(i)),
+            _mm256_loadu_ps(b_ptr.add(i)),
+            sums[0],
+        );
+        i += 8;
+    }
+
+    let sum = _mm256_add_ps(
+        _mm256_add_ps(sums[0], sums[1]),
+        _mm256_add_ps(sums[2], sums[3]),
+    );
+    let sum = _mm_add_ps(_mm256_castps256_ps128(sum), _mm256_extractf128_ps(sum, 1));
+    let sum = _mm_add_ps(sum, _mm_movehl_ps(sum, sum));
+    let sum = _mm_add_ss(sum, _mm_shuffle_ps(sum, sum, 1));
+
+    _mm_cvtss_f32(sum) + dot_scalar(&a[i..n], &b[i..n])
+}
+
+#[cfg(target_arch = "x86_64")]
+fn dot_avx2_checked(a: &[f32], b: &[f32]) -> f32 {
+    // Safety: only returned by `kernel` once AVX2 and FMA are detected.
+    unsafe { dot_avx2(a, b) }
+}
+
+// Picks the fastest dot product the CPU supports at runtime, so one binary
+// runs everywhere while using AVX2 and FMA where available.
+pub fn kernel() -> fn(&[f32], &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return dot_avx2_checked;
+        }
+    }
+
+    dot_unrolled
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    kernel()(a, b)
+}
+
+// The dot product of every row of a row-major matrix with `vector`, the
+// sequential reference for the parallel searches.
+#[cfg(test)]
+pub fn matvec(matrix: &[f32], dim: usize, vector: &[f32]) -> Vec<f32> {
+    if dim == 0 {
+        return vec![];
+    }
+
+    let kernel = kernel();
+    matrix
+        .chunks_exact(dim)
+        .map(|row| kernel(row, vector))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(n: usize, seed: f32) -> Vec<f32> {
+        (0..n).map(|i| ((i as f32 + seed) * 0.37).sin()).collect()
+    }
+
+    #[test]
+    fn test_dot_kernels_agree() {
+        for n in [0, 1, 7, 8, 31, 32, 33, 512, 515] {
+            let (a, b) = (vector(n, 0.0), vector(n, 1.0));
+            let expected = dot_scalar(&a, &b);
+
+            assert!((dot(&a, &b) - expected).abs() < 1e-4, "n={n}");
+            assert!((dot_unrolled(&a, &b) - expected).abs() < 1e-4, "n={n}");
+        }
+    }
+
+    #[test]
+    fn test_matvec() {
+        let matrix = Matrix::from_rows(vec![vec![1.0, 0.0, 2.0], vec![0.5, 0.5, 0.0]]).unwrap();
+
+        assert_eq!(
+            matvec(matrix.as_slice(), 3, &[1.0, 2.0, 3.0]),
+            vec![7.0, 1.5]
+        );
+        assert!(matvec(&[], 0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_matrix() {
+        let mut matrix = Matrix::default();
+        assert_eq!(matrix.len(), 0);
+
+        matrix.extend(&[vec![1.0, 0.0]]).unwrap();
+        matrix.extend(&[vec![0.0, 1.0], vec![0.6, 0.8]]).unwrap();
+        assert_eq!(matrix.len(), 3);
+        assert_eq!(matrix.row(2), &[0.6, 0.8]);
+
+        assert_eq!(
+            matrix.extend(&[vec![1.0, 0.0], vec![1.0]]).unwrap_err(),
+            "Embedding has dimension 1 (expected 2)"
+        );
+        assert_eq!(matrix.len(), 3);
+
+        matrix.set_row(0, &[0.8, 0.6]).unwrap();
+        assert_eq!(matrix.row(0), &[0.8, 0.6]);
+        assert!(matrix.set_row(0, &[1.0]).is_err());
+        assert!(Matrix::from_rows(vec![vec![]]).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+// Classifiers, snapshots and mapped indices are stored in files named after
+// them, so names are restricted to characters that cannot escape their
+// directory. `kind` names what is being named in the error.
+pub fn valid_name(kind: &str, name: &str) -> Result<(), String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid {kind} name '{name}'. Use only letters, digits, '-' and '_'"
+        ))
+    }
+}
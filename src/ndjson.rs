@@ -0,0 +1,131 @@
+use crate::vector_index::TextBody;
+
+pub const MAX_LINE_LENGTH: usize = 16 * 1024 * 1024;
+
+// Splits a byte stream into newline-delimited lines as chunks arrive. Line
+// numbers start at 1 and count blank lines, so they match the client's input.
+// Lines longer than `max_line_length` bytes are rejected rather than buffered.
+pub struct LineSplitter {
+    buf: Vec<u8>,
+    // Bytes of `buf` already known to contain no newline.
+    scanned: usize,
+    line: usize,
+    max_line_length: usize,
+}
+
+impl LineSplitter {
+    pub fn new(max_line_length: usize) -> LineSplitter {
+        LineSplitter {
+            buf: vec![],
+            scanned: 0,
+            line: 0,
+            max_line_length,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<(usize, Vec<u8>)>, String> {
+        self.buf.extend_from_slice(bytes);
+
+        let mut lines = vec![];
+        let mut start = 0;
+        let mut pos = self.scanned;
+        while let Some(offset) = self.buf[pos..].iter().position(|b| *b == b'\n') {
+            let end = pos + offset;
+            self.line += 1;
+            self.check_length(self.line, end - start)?;
+            lines.push((self.line, self.buf[start..end].to_vec()));
+
+            start = end + 1;
+            pos = start;
+        }
+
+        self.buf.drain(..start);
+        self.scanned = self.buf.len();
+        self.check_length(self.line + 1, self.buf.len())?;
+
+        Ok(lines)
+    }
+
+    fn check_length(&self, line: usize, len: usize) -> Result<(), String> {
+        if len > self.max_line_length {
+            return Err(format!(
+                "Line {line} exceeds the maximum length of {} bytes",
+                self.max_line_length
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Option<(usize, Vec<u8>)> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        self.line += 1;
+        Some((self.line, std::mem::take(&mut self.buf)))
+    }
+}
+
+pub fn parse_text_body(line: &[u8]) -> Result<Option<TextBody>, String> {
+    let line = std::str::from_utf8(line).map_err(|err| format!("Invalid UTF-8: {err}"))?;
+
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_str::<TextBody>(line)
+        .map(Some)
+        .map_err(|err| format!("Invalid TextBody: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_splitter_across_chunks() {
+        let mut splitter = LineSplitter::new(MAX_LINE_LENGTH);
+
+        assert!(splitter.push(b"{\"id\":").unwrap().is_empty());
+        assert_eq!(
+            splitter.push(b" \"a\"}\n\n{\"id\"").unwrap(),
+            vec![(1, b"{\"id\": \"a\"}".to_vec()), (2, vec![])]
+        );
+        assert_eq!(splitter.finish(), Some((3, b"{\"id\"".to_vec())));
+    }
+
+    #[test]
+    fn test_line_splitter_without_trailing_line() {
+        let mut splitter = LineSplitter::new(MAX_LINE_LENGTH);
+
+        assert_eq!(splitter.push(b"a\n").unwrap().len(), 1);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn test_line_splitter_rejects_long_lines() {
+        let mut splitter = LineSplitter::new(4);
+
+        assert_eq!(splitter.push(b"abcd\nab").unwrap().len(), 1);
+        assert!(splitter.push(b"c").is_ok());
+        assert_eq!(
+            splitter.push(b"de\n"),
+            Err(String::from("Line 2 exceeds the maximum length of 4 bytes"))
+        );
+
+        let mut splitter = LineSplitter::new(4);
+        assert!(splitter.push(b"abcde").is_err());
+    }
+
+    #[test]
+    fn test_parse_text_body() {
+        let text_body = parse_text_body(b"{\"id\": \"a\", \"text\": \"b\"}")
+            .expect("Could not parse")
+            .expect("Expected a TextBody");
+
+        assert_eq!((text_body.id.as_str(), text_body.text.as_str()), ("a", "b"));
+        assert!(parse_text_body(b"  \r").expect("Could not parse").is_none());
+        assert!(parse_text_body(b"{\"id\": \"a\"}").is_err());
+    }
+}
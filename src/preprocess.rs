@@ -0,0 +1,180 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Replacement {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct PreprocessConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unicode: Option<UnicodeForm>,
+    #[serde(default)]
+    pub strip_html: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip_patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replacements: Vec<Replacement>,
+    #[serde(default)]
+    pub lowercase: bool,
+    #[serde(default)]
+    pub collapse_whitespace: bool,
+}
+
+const HTML_ENTITIES: [(&str, &str); 6] = [
+    ("&nbsp;", " "),
+    ("&lt;", "<"),
+    ("&gt;", ">"),
+    ("&quot;", "\""),
+    ("&#39;", "'"),
+    ("&amp;", "&"),
+];
+
+// Steps run in a fixed order: unicode normalization, HTML stripping,
+// boilerplate stripping, regex replacements, lowercasing and finally
+// whitespace collapsing, so that earlier steps can leave stray whitespace.
+pub struct Pipeline {
+    config: PreprocessConfig,
+    html_tag: Option<Regex>,
+    strip_patterns: Vec<Regex>,
+    replacements: Vec<(Regex, String)>,
+    whitespace: Option<Regex>,
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| format!("Invalid pattern '{pattern}': {err}"))
+}
+
+impl Pipeline {
+    pub fn new(config: PreprocessConfig) -> Result<Pipeline, String> {
+        let html_tag = if config.strip_html {
+            Some(compile(r"<[^>]*>")?)
+        } else {
+            None
+        };
+
+        let strip_patterns = config
+            .strip_patterns
+            .iter()
+            .map(|pattern| compile(pattern))
+            .collect::<Result<Vec<Regex>, String>>()?;
+
+        let replacements = config
+            .replacements
+            .iter()
+            .map(|r| compile(&r.pattern).map(|regex| (regex, r.replacement.clone())))
+            .collect::<Result<Vec<(Regex, String)>, String>>()?;
+
+        let whitespace = if config.collapse_whitespace {
+            Some(compile(r"\s+")?)
+        } else {
+            None
+        };
+
+        Ok(Pipeline {
+            config,
+            html_tag,
+            strip_patterns,
+            replacements,
+            whitespace,
+        })
+    }
+
+    pub fn identity() -> Pipeline {
+        Pipeline::new(PreprocessConfig::default()).unwrap()
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = match self.config.unicode {
+            Some(UnicodeForm::Nfc) => text.nfc().collect(),
+            Some(UnicodeForm::Nfd) => text.nfd().collect(),
+            Some(UnicodeForm::Nfkc) => text.nfkc().collect(),
+            Some(UnicodeForm::Nfkd) => text.nfkd().collect(),
+            None => text.to_string(),
+        };
+
+        if let Some(html_tag) = &self.html_tag {
+            text = html_tag.replace_all(&text, " ").into_owned();
+            for (entity, replacement) in HTML_ENTITIES {
+                text = text.replace(entity, replacement);
+            }
+        }
+
+        for pattern in &self.strip_patterns {
+            text = pattern.replace_all(&text, "").into_owned();
+        }
+
+        for (pattern, replacement) in &self.replacements {
+            text = pattern
+                .replace_all(&text, replacement.as_str())
+                .into_owned();
+        }
+
+        if self.config.lowercase {
+            text = text.to_lowercase();
+        }
+
+        if let Some(whitespace) = &self.whitespace {
+            text = whitespace.replace_all(text.trim(), " ").into_owned();
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_leaves_text_untouched() {
+        let pipeline = Pipeline::identity();
+
+        assert_eq!(pipeline.apply("  Some <b>Text</b> "), "  Some <b>Text</b> ");
+    }
+
+    #[test]
+    fn test_full_pipeline() {
+        let pipeline = Pipeline::new(PreprocessConfig {
+            unicode: Some(UnicodeForm::Nfkc),
+            strip_html: true,
+            strip_patterns: vec![String::from(r"(?i)page \d+ of \d+")],
+            replacements: vec![Replacement {
+                pattern: String::from(r"\bsq\.? ?ft\b"),
+                replacement: String::from("square feet"),
+            }],
+            lowercase: true,
+            collapse_whitespace: true,
+        })
+        .expect("Could not create pipeline");
+
+        assert_eq!(
+            pipeline.apply("<p>Ｇross  Living Area: 1,200 sq ft</p>&amp; Page 3 of 12\n"),
+            "gross living area: 1,200 square feet &"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = Pipeline::new(PreprocessConfig {
+            strip_patterns: vec![String::from("(")],
+            ..PreprocessConfig::default()
+        })
+        .err()
+        .expect("Expected invalid pattern to fail");
+
+        assert!(err.starts_with("Invalid pattern '('"));
+    }
+}
@@ -0,0 +1,506 @@
+use crate::cluster::squared_distance;
+use crate::matrix::dot;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+const POWER_ITERATIONS: usize = 100;
+const POWER_TOLERANCE: f32 = 1e-6;
+
+fn validate(vectors: &[Vec<f32>], dims: usize) -> Result<(), String> {
+    if vectors.is_empty() {
+        return Err(String::from("Cannot project an empty index"));
+    }
+
+    let dim = vectors[0].len();
+    if vectors.iter().any(|v| v.len() != dim) {
+        return Err(String::from("All embeddings must have the same dimensions"));
+    }
+
+    if dims == 0 || dims > dim {
+        return Err(format!("dims must be between 1 and {dim}"));
+    }
+
+    Ok(())
+}
+
+fn squared_norm(v: &[f32]) -> f32 {
+    dot(v, v)
+}
+
+fn normalize(v: &mut [f32]) -> f32 {
+    let norm = squared_norm(v).sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+
+    norm
+}
+
+// Gram-Schmidt against an orthonormal basis, applied twice since a single pass
+// loses orthogonality when most of `v` lies in the basis. Returns the norm of
+// the part of `v` outside the basis.
+fn orthonormalize(v: &mut [f32], basis: &[Vec<f32>]) -> f32 {
+    let mut norm = 0.0;
+    for pass in 0..2 {
+        for b in basis {
+            let overlap = dot(v, b);
+            v.iter_mut().zip(b).for_each(|(x, y)| *x -= overlap * y);
+        }
+
+        let pass_norm = normalize(v);
+        if pass == 0 {
+            norm = pass_norm;
+        }
+    }
+
+    norm
+}
+
+// Principal components found by power iteration on X^T X, deflating against
+// the components already found. X is never materialized as a covariance
+// matrix, so each iteration is linear in the number of vectors.
+pub struct Pca {
+    pub mean: Vec<f32>,
+    pub components: Vec<Vec<f32>>,
+}
+
+impl Pca {
+    pub fn fit(vectors: &[Vec<f32>], dims: usize, seed: u64) -> Result<Pca, String> {
+        validate(vectors, dims)?;
+
+        let dim = vectors[0].len();
+        let mut mean = vec![0.0_f32; dim];
+        for vector in vectors {
+            mean.iter_mut().zip(vector).for_each(|(m, x)| *m += x);
+        }
+        mean.iter_mut().for_each(|m| *m /= vectors.len() as f32);
+
+        let centered: Vec<Vec<f32>> = vectors
+            .iter()
+            .map(|v| v.iter().zip(&mean).map(|(x, m)| x - m).collect())
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut components: Vec<Vec<f32>> = vec![];
+
+        for _ in 0..dims {
+            let mut component: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            orthonormalize(&mut component, &components);
+
+            for _ in 0..POWER_ITERATIONS {
+                let mut next = vec![0.0_f32; dim];
+                for row in &centered {
+                    let projection = dot(row, &component);
+                    next.iter_mut()
+                        .zip(row)
+                        .for_each(|(n, x)| *n += projection * x);
+                }
+
+                if orthonormalize(&mut next, &components) == 0.0 {
+                    break;
+                }
+
+                let change = 1.0 - dot(&next, &component).abs();
+                component = next;
+                if change < POWER_TOLERANCE {
+                    break;
+                }
+            }
+
+            components.push(component);
+        }
+
+        Ok(Pca { mean, components })
+    }
+
+    pub fn transform(&self, vector: &[f32]) -> Vec<f32> {
+        let centered: Vec<f32> = vector.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+
+        self.components
+            .iter()
+            .map(|component| dot(&centered, component))
+            .collect()
+    }
+}
+
+// A PCA fitted on the embeddings of an index, which then stores and searches
+// reduced vectors. Reduced vectors are centered and l2-normalized, so scores
+// remain cosine similarities, now in the reduced space.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Reduction {
+    pub mean: Vec<f32>,
+    pub components: Vec<Vec<f32>>,
+    pub explained_variance_ratio: Vec<f32>,
+}
+
+impl Reduction {
+    pub fn fit(vectors: &[Vec<f32>], dims: usize, seed: u64) -> Result<Reduction, String> {
+        let pca = Pca::fit(vectors, dims, seed)?;
+
+        // The variance along each component, as a fraction of the total
+        // variance of the vectors.
+        let mut variances = vec![0.0_f32; dims];
+        let mut total = 0.0_f32;
+        for vector in vectors {
+            let centered: Vec<f32> = vector.iter().zip(&pca.mean).map(|(x, m)| x - m).collect();
+            total += squared_norm(&centered);
+            for (variance, component) in variances.iter_mut().zip(&pca.components) {
+                *variance += dot(&centered, component).powi(2);
+            }
+        }
+        let explained_variance_ratio = variances
+            .iter()
+            .map(|variance| if total > 0.0 { variance / total } else { 0.0 })
+            .collect();
+
+        Ok(Reduction {
+            mean: pca.mean,
+            components: pca.components,
+            explained_variance_ratio,
+        })
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn input_dimensions(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn explained_variance(&self) -> f32 {
+        self.explained_variance_ratio.iter().sum()
+    }
+
+    pub fn reduce(&self, vector: &[f32]) -> Result<Vec<f32>, String> {
+        if vector.len() != self.input_dimensions() {
+            return Err(format!(
+                "Embedding has dimension {} (expected {})",
+                vector.len(),
+                self.input_dimensions()
+            ));
+        }
+
+        let centered: Vec<f32> = vector.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+        let mut reduced: Vec<f32> = self
+            .components
+            .iter()
+            .map(|component| dot(&centered, component))
+            .collect();
+        normalize(&mut reduced);
+
+        Ok(reduced)
+    }
+
+    // An approximation of the unit vector that was reduced to `reduced`. The
+    // reduced vector only gives the direction of the centered vector, whose
+    // length is the one that puts the result back on the unit sphere.
+    pub fn reconstruct(&self, reduced: &[f32]) -> Vec<f32> {
+        debug_assert_eq!(reduced.len(), self.dimensions());
+
+        let mut direction = vec![0.0_f32; self.input_dimensions()];
+        for (coord, component) in reduced.iter().zip(&self.components) {
+            direction
+                .iter_mut()
+                .zip(component)
+                .for_each(|(x, c)| *x += coord * c);
+        }
+        normalize(&mut direction);
+
+        let along = dot(&self.mean, &direction);
+        let length = (along * along + 1.0 - squared_norm(&self.mean))
+            .max(0.0)
+            .sqrt()
+            - along;
+
+        let mut vector: Vec<f32> = self
+            .mean
+            .iter()
+            .zip(&direction)
+            .map(|(m, d)| m + length * d)
+            .collect();
+        normalize(&mut vector);
+
+        vector
+    }
+}
+
+pub fn pca(vectors: &[Vec<f32>], dims: usize, seed: u64) -> Result<Vec<Vec<f32>>, String> {
+    let pca = Pca::fit(vectors, dims, seed)?;
+
+    Ok(vectors.iter().map(|v| pca.transform(v)).collect())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TsneParams {
+    pub perplexity: f32,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+const TSNE_EXAGGERATION: f32 = 12.0;
+const TSNE_EXAGGERATION_ITERATIONS: usize = 250;
+const TSNE_MIN_GAIN: f32 = 0.01;
+
+// Conditional probabilities p(j|i) for one point, with the Gaussian bandwidth
+// found by binary search so that the distribution has the given perplexity.
+fn conditional_probabilities(distances: &[f32], i: usize, perplexity: f32) -> Vec<f32> {
+    let target_entropy = perplexity.ln();
+    let (mut beta, mut beta_min, mut beta_max) = (1.0_f32, 0.0_f32, f32::INFINITY);
+    let mut probabilities = vec![0.0_f32; distances.len()];
+
+    for _ in 0..50 {
+        let mut total = 0.0;
+        for (j, d) in distances.iter().enumerate() {
+            probabilities[j] = if j == i { 0.0 } else { (-d * beta).exp() };
+            total += probabilities[j];
+        }
+        let total = total.max(f32::MIN_POSITIVE);
+
+        let mut entropy = 0.0;
+        for (j, p) in probabilities.iter_mut().enumerate() {
+            *p /= total;
+            entropy += beta * distances[j] * *p;
+        }
+        entropy += total.ln();
+
+        let diff = entropy - target_entropy;
+        if diff.abs() < 1e-5 {
+            break;
+        }
+
+        if diff > 0.0 {
+            beta_min = beta;
+            beta = if beta_max.is_infinite() {
+                beta * 2.0
+            } else {
+                (beta + beta_max) / 2.0
+            };
+        } else {
+            beta_max = beta;
+            beta = (beta + beta_min) / 2.0;
+        }
+    }
+
+    probabilities
+}
+
+// Exact t-SNE (van der Maaten and Hinton, 2008), initialized from PCA so that
+// results are stable for a given seed. Time and memory are quadratic in the
+// number of vectors, so callers should sample large inputs.
+pub fn tsne(
+    vectors: &[Vec<f32>],
+    dims: usize,
+    params: &TsneParams,
+) -> Result<Vec<Vec<f32>>, String> {
+    validate(vectors, dims)?;
+
+    let n = vectors.len();
+    if (n as f32) < 3.0 * params.perplexity + 1.0 {
+        return Err(format!(
+            "Perplexity {} is too large for {n} embeddings",
+            params.perplexity
+        ));
+    }
+
+    let mut p = vec![0.0_f32; n * n];
+    for i in 0..n {
+        let distances: Vec<f32> = vectors
+            .iter()
+            .map(|v| squared_distance(&vectors[i], v))
+            .collect();
+        let row = conditional_probabilities(&distances, i, params.perplexity);
+        p[i * n..(i + 1) * n].copy_from_slice(&row);
+    }
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let symmetric = ((p[i * n + j] + p[j * n + i]) / (2.0 * n as f32)).max(1e-12);
+            p[i * n + j] = symmetric;
+            p[j * n + i] = symmetric;
+        }
+    }
+
+    // Scale the PCA initialization to a small standard deviation.
+    let mut y = pca(vectors, dims, params.seed)?;
+    let spread = (y.iter().map(|row| row[0] * row[0]).sum::<f32>() / n as f32)
+        .sqrt()
+        .max(f32::MIN_POSITIVE);
+    y.iter_mut()
+        .for_each(|row| row.iter_mut().for_each(|x| *x *= 1e-4 / spread));
+
+    // The learning rate heuristic of Belkina et al. (2019), as used by
+    // scikit-learn.
+    let learning_rate = (n as f32 / TSNE_EXAGGERATION / 4.0).max(50.0);
+    let mut velocity = vec![vec![0.0_f32; dims]; n];
+    let mut gains = vec![vec![1.0_f32; dims]; n];
+    let mut q = vec![0.0_f32; n * n];
+
+    for iteration in 0..params.iterations {
+        let exaggeration = if iteration < TSNE_EXAGGERATION_ITERATIONS {
+            TSNE_EXAGGERATION
+        } else {
+            1.0
+        };
+        let momentum = if iteration < TSNE_EXAGGERATION_ITERATIONS {
+            0.5
+        } else {
+            0.8
+        };
+
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let kernel = 1.0 / (1.0 + squared_distance(&y[i], &y[j]));
+                q[i * n + j] = kernel;
+                q[j * n + i] = kernel;
+                total += 2.0 * kernel;
+            }
+        }
+
+        for i in 0..n {
+            let mut gradient = vec![0.0_f32; dims];
+            for j in 0..n {
+                if i == j {
+                    continue;
+                }
+
+                let kernel = q[i * n + j];
+                let force = 4.0 * (exaggeration * p[i * n + j] - kernel / total) * kernel;
+                for (g, (a, b)) in gradient.iter_mut().zip(y[i].iter().zip(&y[j])) {
+                    *g += force * (a - b);
+                }
+            }
+
+            for d in 0..dims {
+                let same_sign = (gradient[d] > 0.0) == (velocity[i][d] > 0.0);
+                gains[i][d] = if same_sign {
+                    (gains[i][d] * 0.8).max(TSNE_MIN_GAIN)
+                } else {
+                    gains[i][d] + 0.2
+                };
+                velocity[i][d] =
+                    momentum * velocity[i][d] - learning_rate * gains[i][d] * gradient[d];
+            }
+        }
+
+        for (row, step) in y.iter_mut().zip(&velocity) {
+            row.iter_mut().zip(step).for_each(|(x, s)| *x += s);
+        }
+    }
+
+    Ok(y)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ProjectionMethod {
+    Pca { seed: u64 },
+    Tsne(TsneParams),
+}
+
+pub fn project(
+    vectors: &[Vec<f32>],
+    dims: usize,
+    method: ProjectionMethod,
+) -> Result<Vec<Vec<f32>>, String> {
+    match method {
+        ProjectionMethod::Pca { seed } => pca(vectors, dims, seed),
+        ProjectionMethod::Tsne(params) => tsne(vectors, dims, &params),
+    }
+}
+
+// Positions of at most `size` vectors, chosen at random but kept in index
+// order.
+pub fn sample_positions(len: usize, size: usize, seed: u64) -> Vec<usize> {
+    if len <= size {
+        return (0..len).collect();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut positions = rand::seq::index::sample(&mut rng, len, size).into_vec();
+    positions.sort_unstable();
+
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line() -> Vec<Vec<f32>> {
+        (0..10)
+            .map(|i| vec![i as f32, 2.0 * i as f32, 0.5])
+            .collect()
+    }
+
+    #[test]
+    fn test_pca_recovers_main_axis() {
+        let pca = Pca::fit(&line(), 2, 0).expect("Could not fit");
+        let expected = [1.0 / 5.0_f32.sqrt(), 2.0 / 5.0_f32.sqrt(), 0.0];
+
+        assert!((dot(&pca.components[0], &expected).abs() - 1.0).abs() < 1e-4);
+        assert!(dot(&pca.components[0], &pca.components[1]).abs() < 1e-4);
+
+        let projected = pca.transform(&line()[9]);
+        assert!((projected[0].abs() - 4.5 * 5.0_f32.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_pca_validation() {
+        assert!(pca(&[], 2, 0).is_err());
+        assert!(pca(&line(), 4, 0).is_err());
+        assert!(pca(&line(), 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_reduction() {
+        let vectors: Vec<Vec<f32>> = (0..20)
+            .map(|i| {
+                let angle = i as f32 * 0.3;
+                let mut v = vec![angle.cos(), angle.sin(), 0.05 * (i % 3) as f32];
+                normalize(&mut v);
+                v
+            })
+            .collect();
+
+        let reduction = Reduction::fit(&vectors, 2, 0).expect("Could not fit");
+        assert_eq!(reduction.dimensions(), 2);
+        assert_eq!(reduction.input_dimensions(), 3);
+        assert!(reduction.explained_variance() > 0.95);
+        assert!(reduction.explained_variance() <= 1.0 + 1e-4);
+        assert!(reduction.explained_variance_ratio[0] >= reduction.explained_variance_ratio[1]);
+
+        let reduced = reduction.reduce(&vectors[4]).unwrap();
+        assert!((squared_norm(&reduced) - 1.0).abs() < 1e-4);
+        assert!(dot(&reduction.reconstruct(&reduced), &vectors[4]) > 0.99);
+        assert!(reduction.reduce(&[1.0, 0.0]).is_err());
+    }
+
+    #[test]
+    fn test_sample_positions() {
+        assert_eq!(sample_positions(3, 5, 0), vec![0, 1, 2]);
+
+        let positions = sample_positions(100, 10, 0);
+        assert_eq!(positions.len(), 10);
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_tsne_separates_groups() {
+        let mut vectors: Vec<Vec<f32>> = (0..8).map(|i| vec![0.0, 0.01 * i as f32]).collect();
+        vectors.extend((0..8).map(|i| vec![10.0, 0.01 * i as f32]));
+
+        let params = TsneParams {
+            perplexity: 3.0,
+            iterations: 1000,
+            seed: 0,
+        };
+        let y = tsne(&vectors, 2, &params).expect("Could not run t-SNE");
+
+        let within = squared_distance(&y[0], &y[1]);
+        let between = squared_distance(&y[0], &y[8]);
+        assert!(between > 100.0 * within);
+
+        assert!(tsne(&vectors[..4], 2, &params).is_err());
+    }
+}
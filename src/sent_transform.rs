@@ -1,16 +1,103 @@
 mod svm;
 
+use crate::matrix;
+use rayon::prelude::*;
+use sbert::Tokenizer;
+use serde::{Deserialize, Serialize};
 use std::collections::BinaryHeap;
+use std::fs;
+use std::path::Path;
 
 pub type SentenceTransformer = sbert::SBert<sbert::HFTokenizer>;
+pub type SentenceTokenizer = sbert::HFTokenizer;
 
-const BATCH_SIZE: usize = 64;
+pub const BATCH_SIZE: usize = 64;
+
+// Below this many rows, a search is faster on one thread than split across
+// the thread pool.
+pub const PARALLEL_MIN_ROWS: usize = 32_768;
+
+// Models truncate inputs to a maximum number of tokens, including the [CLS]
+// and [SEP] tokens added around each input. sentence-transformers saves it in
+// the model's sentence_bert_config.json; models without one are assumed to
+// truncate like distiluse-base-multilingual-cased-v2, the default model.
+pub const DEFAULT_MAX_SEQUENCE_LENGTH: usize = 128;
+const SENTENCE_BERT_CONFIG: &str = "sentence_bert_config.json";
+pub const SPECIAL_TOKENS: usize = 2;
+
+#[derive(Deserialize)]
+struct SentenceBertConfig {
+    max_seq_length: usize,
+}
 
 pub fn load_model(path: &str) -> Result<SentenceTransformer, sbert::Error> {
-    println!("Laoding SBERT from: {path}");
+    log::info!("Loading SBERT from: {path}");
     sbert::SBertHF::new(path)
 }
 
+pub fn load_max_sequence_length(path: &str) -> Result<usize, String> {
+    let config_path = Path::new(path).join(SENTENCE_BERT_CONFIG);
+    if !config_path.exists() {
+        return Ok(DEFAULT_MAX_SEQUENCE_LENGTH);
+    }
+
+    let config = fs::read_to_string(&config_path)
+        .map_err(|err| format!("Could not read {}: {err}", config_path.display()))?;
+    let config: SentenceBertConfig = serde_json::from_str(&config)
+        .map_err(|err| format!("Could not parse {}: {err}", config_path.display()))?;
+
+    if config.max_seq_length <= SPECIAL_TOKENS {
+        return Err(format!(
+            "Invalid max_seq_length {} in {}",
+            config.max_seq_length,
+            config_path.display()
+        ));
+    }
+
+    Ok(config.max_seq_length)
+}
+
+pub fn load_tokenizer(path: &str) -> Result<SentenceTokenizer, sbert::Error> {
+    SentenceTokenizer::new(path)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TokenCount {
+    pub tokens: usize,
+    pub truncated: bool,
+}
+
+impl TokenCount {
+    fn from_wordpieces(wordpieces: usize, max_sequence_length: usize) -> TokenCount {
+        let tokens = wordpieces + SPECIAL_TOKENS;
+
+        TokenCount {
+            tokens,
+            truncated: tokens > max_sequence_length,
+        }
+    }
+}
+
+// The number of wordpieces of each input, without the special tokens.
+pub fn count_wordpieces(tokenizer: &SentenceTokenizer, input: &[&str]) -> Vec<usize> {
+    tokenizer
+        .pre_tokenize(input)
+        .iter()
+        .map(|wordpieces| wordpieces.len())
+        .collect()
+}
+
+pub fn count_tokens(
+    tokenizer: &SentenceTokenizer,
+    input: &[&str],
+    max_sequence_length: usize,
+) -> Vec<TokenCount> {
+    count_wordpieces(tokenizer, input)
+        .into_iter()
+        .map(|wordpieces| TokenCount::from_wordpieces(wordpieces, max_sequence_length))
+        .collect()
+}
+
 pub fn compute_normalized_embeddings(
     model: &SentenceTransformer,
     input: &[&str],
@@ -30,7 +117,13 @@ pub fn compute_normalized_embedding(
     compute_normalized_embeddings(model, &[input]).map(|e| e.first().unwrap().clone())
 }
 
-#[derive(PartialEq)]
+// The model config does not record the output dimension, which depends on
+// any dense layers after pooling, so it is read off a probe embedding.
+pub fn embedding_dimensions(model: &SentenceTransformer) -> Result<usize, sbert::Error> {
+    compute_normalized_embedding(model, "dimensions").map(|embedding| embedding.len())
+}
+
+#[derive(PartialEq, Serialize)]
 pub struct IndexWithScore {
     pub index: usize,
     pub score: f32,
@@ -56,76 +149,150 @@ impl Ord for IndexWithScore {
     }
 }
 
+// Keeps the `results` highest scores in a heap whose top is the lowest of them.
+fn push_top_k(heap: &mut BinaryHeap<IndexWithScore>, item: IndexWithScore, results: usize) {
+    if heap.len() < results {
+        heap.push(item);
+    } else if matches!(heap.peek(), Some(min_elem) if min_elem.score < item.score) {
+        heap.pop();
+        heap.push(item);
+    }
+}
+
+fn into_descending(heap: BinaryHeap<IndexWithScore>) -> Vec<IndexWithScore> {
+    let mut items = heap.into_vec();
+    items.sort();
+
+    items
+}
+
+// The `results` highest scores with their positions, in descending order.
+fn top_k(scores: impl Iterator<Item = f32>, results: usize) -> Vec<IndexWithScore> {
+    let mut heap: BinaryHeap<IndexWithScore> = BinaryHeap::new();
+
+    for (index, score) in scores.enumerate() {
+        push_top_k(&mut heap, IndexWithScore { index, score }, results);
+    }
+
+    into_descending(heap)
+}
+
+// Like `top_k`, scoring rows on demand. Searches over at least
+// PARALLEL_MIN_ROWS rows are split across the thread pool, with a heap per
+// thread, and the heaps are merged at the end.
+pub fn top_k_rows(
+    rows: usize,
+    results: usize,
+    score: impl Fn(usize) -> f32 + Sync,
+) -> Vec<IndexWithScore> {
+    if rows < PARALLEL_MIN_ROWS {
+        return top_k((0..rows).map(score), results);
+    }
+
+    let heap = (0..rows)
+        .into_par_iter()
+        .fold(BinaryHeap::new, |mut heap, index| {
+            let score = score(index);
+            push_top_k(&mut heap, IndexWithScore { index, score }, results);
+            heap
+        })
+        .reduce(BinaryHeap::new, |mut heap, other| {
+            for item in other {
+                push_top_k(&mut heap, item, results);
+            }
+            heap
+        });
+
+    into_descending(heap)
+}
+
 pub fn search_knn(
     query: &sbert::Embeddings,
     vectors: &[sbert::Embeddings],
     results: usize,
 ) -> Result<Vec<IndexWithScore>, String> {
-    let mut heap: BinaryHeap<IndexWithScore> = BinaryHeap::new();
-
-    for (index, vector) in vectors.iter().enumerate() {
-        let score = match dot(query, vector) {
-            Ok(s) => s,
-            Err(err) => return Err(err),
-        };
-
-        let new_item = IndexWithScore { index, score };
+    for vector in vectors {
+        check_equal_len(query, vector)?;
+    }
 
-        match heap.peek() {
-            Some(min_elem) => {
-                let curr_len = heap.len();
-                if min_elem.score < score || curr_len < results {
-                    if curr_len == results {
-                        heap.pop();
-                    }
+    Ok(top_k_rows(vectors.len(), results, |row| {
+        matrix::dot(query, &vectors[row])
+    }))
+}
 
-                    heap.push(new_item);
-                }
-            }
-            None => heap.push(new_item),
-        }
+// Like `search_knn`, over the rows of a row-major matrix.
+pub fn search_knn_matrix(
+    query: &[f32],
+    matrix: &[f32],
+    dim: usize,
+    results: usize,
+) -> Result<Vec<IndexWithScore>, String> {
+    if !matrix.is_empty() && query.len() != dim {
+        return Err(format!(
+            "Vectors not equal length (a={}, b={dim})",
+            query.len()
+        ));
     }
 
-    let mut items = heap.into_vec();
-    items.reverse();
+    let kernel = matrix::kernel();
+    let rows = matrix.len().checked_div(dim).unwrap_or(0);
 
-    Ok(items)
+    Ok(top_k_rows(rows, results, |row| {
+        kernel(&matrix[row * dim..(row + 1) * dim], query)
+    }))
 }
 
-pub fn search_exemplar_svm(
+pub fn search_exemplar_svm<V: AsRef<[f32]>>(
     query: &sbert::Embeddings,
-    vectors: &[sbert::Embeddings],
+    vectors: &[V],
     results: usize,
 ) -> Result<Vec<IndexWithScore>, String> {
     let dists = svm::svm(query, vectors)?;
 
-    let mut heap: BinaryHeap<IndexWithScore> = BinaryHeap::new();
-    for (index, dist) in dists.iter().enumerate() {
-        let score = *dist as f32;
-        let new_item = IndexWithScore { index, score };
-
-        match heap.peek() {
-            Some(min_elem) => {
-                let curr_len = heap.len();
-                if min_elem.score < score || curr_len < results {
-                    if curr_len == results {
-                        heap.pop();
-                    }
-
-                    heap.push(new_item);
-                }
-            }
-            None => heap.push(new_item),
-        }
+    Ok(top_k(dists.iter().map(|dist| *dist as f32), results))
+}
+
+pub fn similarity_matrix(
+    a: &[sbert::Embeddings],
+    b: &[sbert::Embeddings],
+) -> Result<Vec<Vec<f32>>, String> {
+    a.iter()
+        .map(|x| b.iter().map(|y| dot(x, y)).collect())
+        .collect()
+}
+
+pub fn paired_similarities(
+    a: &[sbert::Embeddings],
+    b: &[sbert::Embeddings],
+) -> Result<Vec<f32>, String> {
+    if a.len() != b.len() {
+        return Err(format!(
+            "Paired inputs not equal length (a={}, b={})",
+            a.len(),
+            b.len()
+        ));
     }
 
-    let mut items = heap.into_vec();
-    items.reverse();
+    a.iter().zip(b.iter()).map(|(x, y)| dot(x, y)).collect()
+}
 
-    Ok(items)
+// The k most similar rows of `b` for every row of `a`, in descending order.
+pub fn top_k_similarities(
+    a: &[sbert::Embeddings],
+    b: &[sbert::Embeddings],
+    k: usize,
+) -> Result<Vec<Vec<IndexWithScore>>, String> {
+    a.iter()
+        .map(|x| {
+            search_knn(x, b, k).map(|mut items| {
+                items.sort();
+                items
+            })
+        })
+        .collect()
 }
 
-fn dot(a: &sbert::Embeddings, b: &sbert::Embeddings) -> Result<f32, String> {
+fn check_equal_len(a: &[f32], b: &[f32]) -> Result<(), String> {
     if a.len() != b.len() {
         return Err(format!(
             "Vectors not equal length (a={}, b={})",
@@ -134,15 +301,16 @@ fn dot(a: &sbert::Embeddings, b: &sbert::Embeddings) -> Result<f32, String> {
         ));
     }
 
-    let dotp: f32 = a
-        .iter()
-        .zip(b.iter())
-        .fold(0.0, |sum, (ae, be)| sum + (ae * be));
+    Ok(())
+}
+
+fn dot(a: &[f32], b: &[f32]) -> Result<f32, String> {
+    check_equal_len(a, b)?;
 
-    Ok(dotp)
+    Ok(matrix::dot(a, b))
 }
 
-fn l2_normalize(v: Vec<f32>) -> Vec<f32> {
+pub fn l2_normalize(v: Vec<f32>) -> Vec<f32> {
     let norm = l2_norm(&v);
 
     v.iter().map(|elem| elem / norm).collect()
@@ -176,6 +344,26 @@ mod tests {
         assert_eq!(dotp, -1.0);
     }
 
+    #[test]
+    fn test_similarities() {
+        let a = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let b = vec![vec![0.0, 1.0], vec![1.0, 0.0], l2_normalize(vec![1.0, 1.0])];
+
+        let matrix = similarity_matrix(&a, &b).unwrap();
+        assert_eq!(matrix.len(), 2);
+        assert_eq!(&matrix[0][..2], &[0.0, 1.0]);
+
+        assert_eq!(paired_similarities(&a, &b[..2]).unwrap(), vec![0.0, 0.0]);
+        assert!(paired_similarities(&a, &b).is_err());
+
+        let top: Vec<Vec<usize>> = top_k_similarities(&a, &b, 2)
+            .unwrap()
+            .iter()
+            .map(|row| row.iter().map(|i| i.index).collect())
+            .collect();
+        assert_eq!(top, vec![vec![1, 2], vec![0, 2]]);
+    }
+
     #[test]
     fn test_l2_norm() {
         let a: sbert::Embeddings = vec![1.0, 1.0];
@@ -191,6 +379,46 @@ mod tests {
         assert_eq!(l2_norm(&a), 1.0);
     }
 
+    #[test]
+    fn test_token_count_truncation() {
+        assert_eq!(
+            TokenCount::from_wordpieces(126, 128),
+            TokenCount {
+                tokens: 128,
+                truncated: false
+            }
+        );
+        assert!(TokenCount::from_wordpieces(127, 128).truncated);
+        assert!(!TokenCount::from_wordpieces(127, 256).truncated);
+    }
+
+    #[test]
+    fn test_load_max_sequence_length() {
+        let dir = std::env::temp_dir().join("scout-sent-transform-max-sequence-length");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join(SENTENCE_BERT_CONFIG);
+        let path = dir.to_str().unwrap();
+
+        let _ = fs::remove_file(&config);
+        assert_eq!(
+            load_max_sequence_length(path),
+            Ok(DEFAULT_MAX_SEQUENCE_LENGTH)
+        );
+
+        fs::write(
+            &config,
+            r#"{"max_seq_length": 256, "do_lower_case": false}"#,
+        )
+        .unwrap();
+        assert_eq!(load_max_sequence_length(path), Ok(256));
+
+        fs::write(&config, r#"{"do_lower_case": false}"#).unwrap();
+        assert!(load_max_sequence_length(path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_ordering_index_with_score() {
         let mut items = vec![
@@ -246,6 +474,28 @@ mod tests {
         assert_eq!(result_indices, vec![0, 2]);
     }
 
+    #[test]
+    fn test_search_knn_parallel() {
+        let rows = PARALLEL_MIN_ROWS + 5;
+        let dim = 4;
+        let matrix: Vec<f32> = (0..rows * dim)
+            .map(|i| ((i * 7919) % 1000) as f32 / 1000.0)
+            .collect();
+        let query = vec![0.1, 0.7, 0.2, 0.4];
+
+        let serial: Vec<usize> = top_k(matrix::matvec(&matrix, dim, &query).into_iter(), 5)
+            .iter()
+            .map(|i| i.index)
+            .collect();
+        let parallel: Vec<usize> = search_knn_matrix(&query, &matrix, dim, 5)
+            .expect("search_knn_matrix: Unexpected failure")
+            .iter()
+            .map(|i| i.index)
+            .collect();
+
+        assert_eq!(parallel, serial);
+    }
+
     #[test]
     fn test_search_exemplar_svm() {
         let q = l2_normalize(vec![1.0, 0.0]);
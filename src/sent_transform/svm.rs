@@ -1,4 +1,6 @@
+use super::PARALLEL_MIN_ROWS;
 use liblinear::*;
+use rayon::prelude::*;
 
 fn vec_to_features(vec: &[f32]) -> Vec<(u32, f64)> {
     vec.iter()
@@ -7,16 +9,37 @@ fn vec_to_features(vec: &[f32]) -> Vec<(u32, f64)> {
         .collect()
 }
 
-pub fn svm(q: &[f32], vectors: &[Vec<f32>]) -> Result<Vec<f64>, String> {
-    let nvecs = vectors.len();
+// A trained liblinear model shared by the threads predicting with it. The
+// model type returned by `Builder::build_model` cannot be named, so the impl
+// is bounded by `LibLinearModel`, which only liblinear's model implements.
+struct SharedModel<'a, M: LibLinearModel>(&'a M);
+
+// Safety: the model wraps a pointer to a C `struct model` that is only
+// written while training. `predict_values` passes it to liblinear's
+// `predict_values(const struct model *, ...)`, which reads the weights and
+// writes to buffers allocated per call, and touches no global state. Once
+// built, the model is never mutated, so concurrent predictions through a
+// shared reference do not race.
+unsafe impl<M: LibLinearModel> Sync for SharedModel<'_, M> {}
+
+impl<M: LibLinearModel> SharedModel<'_, M> {
+    fn predict_value(&self, input: util::PredictionInput) -> Result<f64, String> {
+        self.0
+            .predict_values(input)
+            .map(|(dists, _)| dists.first().map_or(-100.0, |f| *f))
+            .map_err(|err| err.to_string())
+    }
+}
+
+fn train(q: &[f32], features: &[Vec<(u32, f64)>]) -> Result<impl LibLinearModel, String> {
+    let nvecs = features.len();
 
     let mut labels = vec![0.0; nvecs + 1];
     labels[0] = 1.0;
 
-    let mut all_embeddings: Vec<Vec<(u32, f64)>> =
-        vectors.iter().map(|vec| vec_to_features(vec)).collect();
-    let indexed_embeddings = all_embeddings.clone();
-    all_embeddings.insert(0, vec_to_features(q));
+    let mut all_embeddings: Vec<Vec<(u32, f64)>> = Vec::with_capacity(nvecs + 1);
+    all_embeddings.push(vec_to_features(q));
+    all_embeddings.extend_from_slice(features);
 
     let mut model_builder = liblinear::Builder::new();
 
@@ -36,22 +59,71 @@ pub fn svm(q: &[f32], vectors: &[Vec<f32>]) -> Result<Vec<f64>, String> {
         .cost_penalty_labels(vec![0, 1])
         .cost_penalty_weights(weights);
 
-    let model = model_builder
+    model_builder
         .build_model()
-        .map_err(|err| format!("svm: Error creating model: {err}"))?;
+        .map_err(|err| format!("svm: Error creating model: {err}"))
+}
+
+fn predict_all<M: LibLinearModel>(
+    model: &M,
+    features: &[Vec<(u32, f64)>],
+    parallel: bool,
+) -> Result<Vec<f64>, String> {
+    let model = SharedModel(model);
+    let predict = |(idx, feature): (usize, &Vec<(u32, f64)>)| {
+        util::PredictionInput::from_sparse_features(feature.to_vec())
+            .map_err(|err| format!("svm: Failed to create prediction input {idx}: {err}"))
+            .and_then(|input| {
+                model
+                    .predict_value(input)
+                    .map_err(|err| format!("svm: Failed to predict values input {idx}: {err}"))
+            })
+    };
+
+    if parallel {
+        features.par_iter().enumerate().map(predict).collect()
+    } else {
+        features.iter().enumerate().map(predict).collect()
+    }
+}
 
-    indexed_embeddings
+pub fn svm<V: AsRef<[f32]>>(q: &[f32], vectors: &[V]) -> Result<Vec<f64>, String> {
+    let features: Vec<Vec<(u32, f64)>> = vectors
         .iter()
-        .enumerate()
-        .map(|(idx, feature)| {
-            util::PredictionInput::from_sparse_features(feature.to_vec())
-                .map_err(|err| format!("svm: Failed to create prediction input {idx}: {err}"))
-                .and_then(|input| {
-                    model
-                        .predict_values(input)
-                        .map_err(|err| format!("svm: Failed to predict values input {idx}: {err}"))
-                        .map(|(dists, _)| dists.first().map_or(-100.0, |f| *f))
-                })
-        })
-        .collect()
+        .map(|vec| vec_to_features(vec.as_ref()))
+        .collect();
+    let model = train(q, &features)?;
+
+    predict_all(&model, &features, features.len() >= PARALLEL_MIN_ROWS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predict_parallel() {
+        let rows = PARALLEL_MIN_ROWS + 5;
+        let vectors: Vec<Vec<f32>> = (0..rows)
+            .map(|i| {
+                let angle = i as f32 * 0.001;
+                vec![angle.cos(), angle.sin(), (i % 7) as f32 * 0.1]
+            })
+            .collect();
+        let features: Vec<Vec<(u32, f64)>> =
+            vectors.iter().map(|vec| vec_to_features(vec)).collect();
+        let model = train(&[1.0, 0.0, 0.0], &features).expect("Could not train");
+
+        let sequential = predict_all(&model, &features, false).expect("Could not predict");
+        let parallel = predict_all(&model, &features, true).expect("Could not predict");
+        assert_eq!(parallel.len(), rows);
+        assert_eq!(parallel, sequential);
+
+        assert_eq!(
+            svm(&[1.0, 0.0, 0.0], &vectors)
+                .expect("Could not predict")
+                .len(),
+            rows
+        );
+    }
 }
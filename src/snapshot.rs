@@ -0,0 +1,356 @@
+use crate::export::npy_header;
+use crate::import::{parse_npy, parse_texts};
+use crate::names::valid_name;
+use crate::projection::Reduction;
+use crate::vector_index::{IndexSettings, TextBody};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_EXTENSION: &str = "tar.gz";
+const MANIFEST: &str = "manifest.json";
+const SETTINGS: &str = "settings.json";
+const TEXTS: &str = "texts.jsonl";
+const EMBEDDINGS: &str = "embeddings.npy";
+const REDUCTION: &str = "reduction.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub index: String,
+    pub version: u32,
+    pub size: usize,
+    pub dimensions: usize,
+    pub created: u64,
+}
+
+pub struct Snapshot {
+    pub info: SnapshotInfo,
+    pub settings: IndexSettings,
+    pub reduction: Option<Reduction>,
+    pub texts: Vec<TextBody>,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+// The default name of a snapshot, made of the index name with any character
+// not allowed in snapshot names replaced, and the time it was taken.
+pub fn default_name(index_name: &str, created: u64) -> String {
+    let index_name: String = index_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{index_name}-{created}")
+}
+
+fn append<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<(), String> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime / 1000);
+    header.set_cksum();
+
+    archive
+        .append_data(&mut header, path, data)
+        .map_err(|err| format!("Could not write {path}: {err}"))
+}
+
+// Snapshots are gzipped tar archives saved to `{dir}/{name}.tar.gz`, holding
+// a manifest, the index settings, its entries as JSONL, its embeddings as an
+// npy matrix and, for reduced indices, the PCA, so they can be inspected with
+// standard tools. Archives are written to a temporary file first, so a
+// snapshot is either complete or missing.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn open(dir: &str) -> Result<SnapshotStore, String> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Could not create snapshot directory: {err}"))?;
+
+        Ok(SnapshotStore { dir })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{SNAPSHOT_EXTENSION}"))
+    }
+
+    pub fn write(
+        &self,
+        name: Option<&str>,
+        index_name: &str,
+        settings: &IndexSettings,
+        reduction: Option<&Reduction>,
+        texts: &[TextBody],
+        embeddings: &[Vec<f32>],
+    ) -> Result<SnapshotInfo, String> {
+        let created = now_millis();
+        let name = name.map_or_else(|| default_name(index_name, created), String::from);
+        valid_name("snapshot", &name)?;
+
+        let path = self.path(&name);
+        if path.exists() {
+            return Err(format!("Snapshot {name} already exists"));
+        }
+
+        let dimensions = embeddings.first().map_or(0, |embedding| embedding.len());
+        let info = SnapshotInfo {
+            name: name.clone(),
+            index: String::from(index_name),
+            version: SNAPSHOT_VERSION,
+            size: texts.len(),
+            dimensions,
+            created,
+        };
+
+        let mut jsonl: Vec<u8> = vec![];
+        for text_body in texts {
+            serde_json::to_writer(&mut jsonl, text_body)
+                .map_err(|err| format!("Could not encode {}: {err}", text_body.id))?;
+            jsonl.push(b'\n');
+        }
+
+        let mut npy = npy_header(embeddings.len(), dimensions);
+        for embedding in embeddings {
+            for x in embedding {
+                npy.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+
+        let tmp_path = self.dir.join(format!(".{name}.tmp"));
+        let result = File::create(&tmp_path)
+            .map_err(|err| format!("Could not create snapshot: {err}"))
+            .and_then(|file| {
+                let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+                let manifest = serde_json::to_vec(&info).map_err(|err| err.to_string())?;
+                let settings = serde_json::to_vec(settings).map_err(|err| err.to_string())?;
+                append(&mut archive, MANIFEST, &manifest, created)?;
+                append(&mut archive, SETTINGS, &settings, created)?;
+                append(&mut archive, TEXTS, &jsonl, created)?;
+                append(&mut archive, EMBEDDINGS, &npy, created)?;
+                if let Some(reduction) = reduction {
+                    let reduction = serde_json::to_vec(reduction).map_err(|err| err.to_string())?;
+                    append(&mut archive, REDUCTION, &reduction, created)?;
+                }
+
+                archive
+                    .into_inner()
+                    .and_then(|encoder| encoder.finish())
+                    .and_then(|file| file.sync_all())
+                    .map_err(|err| format!("Could not write snapshot: {err}"))
+            })
+            .and_then(|_| {
+                fs::rename(&tmp_path, &path)
+                    .map_err(|err| format!("Could not save snapshot: {err}"))
+            });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+
+        result.map(|_| info)
+    }
+
+    pub fn read(&self, name: &str) -> Result<Snapshot, String> {
+        valid_name("snapshot", name)?;
+
+        let file = File::open(self.path(name)).map_err(|_| format!("Snapshot {name} not found"))?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+        let mut manifest: Option<SnapshotInfo> = None;
+        let mut settings: Option<IndexSettings> = None;
+        let mut texts: Option<Vec<TextBody>> = None;
+        let mut embeddings: Option<Vec<Vec<f32>>> = None;
+        let mut reduction: Option<Reduction> = None;
+
+        let invalid = |err: String| format!("Invalid snapshot {name}: {err}");
+        let entries = archive.entries().map_err(|err| invalid(err.to_string()))?;
+        for entry in entries {
+            let mut entry = entry.map_err(|err| invalid(err.to_string()))?;
+            let path = entry
+                .path()
+                .map_err(|err| invalid(err.to_string()))?
+                .to_string_lossy()
+                .to_string();
+
+            let mut data: Vec<u8> = vec![];
+            entry
+                .read_to_end(&mut data)
+                .map_err(|err| invalid(err.to_string()))?;
+
+            match path.as_str() {
+                MANIFEST => {
+                    manifest = Some(
+                        serde_json::from_slice(&data).map_err(|err| invalid(err.to_string()))?,
+                    )
+                }
+                SETTINGS => {
+                    settings = Some(
+                        serde_json::from_slice(&data).map_err(|err| invalid(err.to_string()))?,
+                    )
+                }
+                TEXTS => texts = Some(parse_texts(&data).map_err(invalid)?),
+                EMBEDDINGS => embeddings = Some(parse_npy(&data).map_err(invalid)?),
+                REDUCTION => {
+                    reduction = Some(
+                        serde_json::from_slice(&data).map_err(|err| invalid(err.to_string()))?,
+                    )
+                }
+                _ => (),
+            }
+        }
+
+        let (info, settings, texts, embeddings) = match (manifest, settings, texts, embeddings) {
+            (Some(info), Some(settings), Some(texts), Some(embeddings)) => {
+                (info, settings, texts, embeddings)
+            }
+            _ => return Err(invalid(String::from("missing archive entries"))),
+        };
+
+        if info.version > SNAPSHOT_VERSION {
+            return Err(invalid(format!("unsupported version {}", info.version)));
+        }
+        if texts.len() != info.size || embeddings.len() != info.size {
+            return Err(invalid(format!(
+                "expected {} entries, found {} texts and {} embeddings",
+                info.size,
+                texts.len(),
+                embeddings.len()
+            )));
+        }
+
+        Ok(Snapshot {
+            info,
+            settings,
+            reduction,
+            texts,
+            embeddings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::DedupeConfig;
+
+    fn temp_store(name: &str) -> SnapshotStore {
+        let dir = std::env::temp_dir().join(format!("scout-snapshots-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+
+        SnapshotStore::open(&dir.to_string_lossy()).expect("Could not open store")
+    }
+
+    #[test]
+    fn test_default_name() {
+        assert_eq!(default_name("my index/v2", 17), "my_index_v2-17");
+        assert!(valid_name("snapshot", &default_name("my index/v2", 17)).is_ok());
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let store = temp_store("roundtrip");
+        let settings = IndexSettings {
+            dedupe: Some(DedupeConfig {
+                threshold: 0.9,
+                mode: Default::default(),
+            }),
+            ..Default::default()
+        };
+        let texts = vec![
+            TextBody {
+                id: String::from("a"),
+                text: String::from("first"),
+                parent: None,
+            },
+            TextBody {
+                id: String::from("b#0"),
+                text: String::from("second"),
+                parent: Some(String::from("b")),
+            },
+        ];
+        let embeddings = vec![vec![1.0, 0.0], vec![0.6, -0.8]];
+        let reduction = Reduction {
+            mean: vec![0.1, 0.0, 0.0],
+            components: vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]],
+            explained_variance_ratio: vec![0.7, 0.2],
+        };
+
+        let info = store
+            .write(
+                Some("staging"),
+                "shakespeare",
+                &settings,
+                Some(&reduction),
+                &texts,
+                &embeddings,
+            )
+            .expect("Could not write snapshot");
+        assert_eq!(info.size, 2);
+        assert_eq!(info.dimensions, 2);
+        assert!(store
+            .write(
+                Some("staging"),
+                "shakespeare",
+                &settings,
+                None,
+                &texts,
+                &embeddings
+            )
+            .is_err());
+
+        let snapshot = store.read("staging").expect("Could not read snapshot");
+        assert_eq!(snapshot.info, info);
+        assert_eq!(snapshot.settings, settings);
+        assert_eq!(snapshot.reduction, Some(reduction));
+        assert_eq!(snapshot.texts[1].parent.as_deref(), Some("b"));
+        assert_eq!(snapshot.embeddings, embeddings);
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let store = temp_store("invalid");
+
+        assert!(store.read("../escape").is_err());
+        assert_eq!(
+            store.read("missing").err().unwrap(),
+            "Snapshot missing not found"
+        );
+        assert!(store
+            .write(
+                Some("a/b"),
+                "index",
+                &IndexSettings::default(),
+                None,
+                &[],
+                &[]
+            )
+            .is_err());
+    }
+}
@@ -1,13 +1,26 @@
-use crate::sent_transform;
+use crate::anomaly::{self, AnomalyMethod};
+use crate::cluster::{self, ClusterCount, KMeansParams, KScore, Linkage, Merge};
+use crate::evaluation::{self, ApproximateMethod, Latency, RecallReport};
+use crate::lsh::{self, LshIndex};
+use crate::mapped::MappedMatrix;
+use crate::matrix::{self, Matrix};
+use crate::preprocess::{Pipeline, PreprocessConfig};
+use crate::projection::{self, ProjectionMethod, Reduction};
+use crate::quantize::BinaryCodes;
+use crate::sent_transform::{self, IndexWithScore};
 use sbert::{self, Embeddings};
 use serde::{Deserialize, Serialize};
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
+use std::collections::{HashMap, HashSet};
 use std::sync;
+use std::time::Instant;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct TextBody {
     pub id: String,
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -15,6 +28,45 @@ pub struct SearchResult {
     pub id: String,
     pub text: String,
     pub score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+// Keeps only the best-scoring chunk of each parent document. Expects results
+// sorted in descending order.
+pub fn collapse_by_parent(results: Vec<SearchResult>, n: usize) -> Vec<SearchResult> {
+    let mut seen: HashSet<String> = HashSet::new();
+
+    results
+        .into_iter()
+        .filter(|result| {
+            let key = result.parent.as_ref().unwrap_or(&result.id);
+            seen.insert(key.clone())
+        })
+        .take(n)
+        .collect()
+}
+
+// A parent's chunks can crowd other documents out of the top n, so collapsed
+// searches fetch this many times n results, and widen the search until n
+// parents are found or the whole index of `len` entries has been ranked.
+const COLLAPSE_OVERSAMPLE: usize = 4;
+
+pub fn search_collapsed<F>(n: usize, len: usize, mut search: F) -> Result<Vec<SearchResult>, String>
+where
+    F: FnMut(usize) -> Result<Vec<SearchResult>, String>,
+{
+    let mut nsearch = n.saturating_mul(COLLAPSE_OVERSAMPLE).min(len);
+    loop {
+        let results = search(nsearch)?;
+        let exhausted = results.len() < nsearch || nsearch >= len;
+        let collapsed = collapse_by_parent(results, n);
+        if collapsed.len() >= n || exhausted {
+            return Ok(collapsed);
+        }
+
+        nsearch = nsearch.saturating_mul(COLLAPSE_OVERSAMPLE).min(len);
+    }
 }
 
 impl PartialEq for SearchResult {
@@ -44,13 +96,375 @@ impl Ord for SearchResult {
     }
 }
 
+#[derive(Serialize, Debug)]
+pub struct ClusterSummary {
+    pub cluster: usize,
+    pub size: usize,
+    pub centroid: Vec<f32>,
+    pub representative: Option<TextBody>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ClusterAssignment {
+    pub id: String,
+    pub cluster: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Clustering {
+    pub k: usize,
+    pub iterations: usize,
+    pub inertia: f32,
+    pub clusters: Vec<ClusterSummary>,
+    pub assignments: Vec<ClusterAssignment>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub k_scores: Vec<KScore>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hierarchy {
+    pub leaves: Vec<String>,
+    pub merges: Vec<Merge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assignments: Vec<ClusterAssignment>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub k_scores: Vec<KScore>,
+}
+
+fn cluster_assignments(assignments: &[usize], texts: &[TextBody]) -> Vec<ClusterAssignment> {
+    assignments
+        .iter()
+        .zip(texts.iter())
+        .map(|(cluster, text_body)| ClusterAssignment {
+            id: text_body.id.clone(),
+            cluster: *cluster,
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProjectedPoint {
+    pub id: String,
+    pub coords: Vec<f32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Projection {
+    pub dims: usize,
+    pub total: usize,
+    pub sampled: bool,
+    pub points: Vec<ProjectedPoint>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DuplicateGroup {
+    pub canonical: TextBody,
+    pub duplicates: Vec<SearchResult>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeMode {
+    #[default]
+    Skip,
+    Merge,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DedupeConfig {
+    pub threshold: f32,
+    #[serde(default)]
+    pub mode: DedupeMode,
+}
+
+pub const DEFAULT_OVERSAMPLE: f32 = 4.0;
+
+fn default_oversample() -> f32 {
+    DEFAULT_OVERSAMPLE
+}
+
+// Cosine searches first take `oversample` times the requested number of
+// results by the Hamming distance between sign-bit codes, then rescore them
+// with the full embeddings.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct QuantizationConfig {
+    #[serde(default = "default_oversample")]
+    pub oversample: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct IndexSettings {
+    #[serde(default)]
+    pub preprocess: PreprocessConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedupe: Option<DedupeConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<QuantizationConfig>,
+}
+
+pub fn validate_oversample(oversample: f32) -> Result<(), String> {
+    if oversample.is_finite() && oversample >= 1.0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid oversample {oversample}. Must be at least 1"
+        ))
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Deduplicated {
+    pub id: String,
+    pub duplicate_of: String,
+    pub score: f32,
+}
+
+// Embeddings are either owned, and can be appended to, or memory-mapped from
+// a file, which makes the index read-only. Both are row-major matrices.
+enum Storage {
+    Owned(Matrix),
+    Mapped(MappedMatrix),
+}
+
+impl Storage {
+    fn len(&self) -> usize {
+        match self {
+            Storage::Owned(matrix) => matrix.len(),
+            Storage::Mapped(matrix) => matrix.len(),
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        match self {
+            Storage::Owned(matrix) => matrix.dimensions(),
+            Storage::Mapped(matrix) => matrix.dimensions(),
+        }
+    }
+
+    fn as_slice(&self) -> &[f32] {
+        match self {
+            Storage::Owned(matrix) => matrix.as_slice(),
+            Storage::Mapped(matrix) => matrix.as_slice(),
+        }
+    }
+
+    fn row(&self, row: usize) -> &[f32] {
+        match self {
+            Storage::Owned(matrix) => matrix.row(row),
+            Storage::Mapped(matrix) => matrix.row(row),
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[f32]> + '_ {
+        (0..self.len()).map(|row| self.row(row))
+    }
+
+    // Borrows every row, for analyses that index embeddings by position.
+    fn row_slices(&self) -> Vec<&[f32]> {
+        self.rows().collect()
+    }
+
+    fn owned_mut(&mut self) -> Result<&mut Matrix, String> {
+        match self {
+            Storage::Owned(matrix) => Ok(matrix),
+            Storage::Mapped(_) => Err(String::from("Memory-mapped indices are read-only")),
+        }
+    }
+}
+
+// The approximate search of a recall evaluation.
+enum Approximation<'a> {
+    Binary {
+        codes: &'a BinaryCodes,
+        oversample: f32,
+    },
+    Reduced {
+        reduction: Reduction,
+        reduced: Matrix,
+    },
+}
+
 struct Index {
     pub texts: Vec<TextBody>,
-    pub embeddings: Vec<sbert::Embeddings>,
+    embeddings: Storage,
+    dedupe: Option<LshIndex>,
+    binary: Option<BinaryCodes>,
+    reduction: Option<Reduction>,
+    // Counts changes to existing entries, so that readers spanning several
+    // locks, like exports, can tell that rows they already read are stale.
+    // Appends leave existing entries alone and are not counted.
+    generation: u64,
+}
+
+impl Index {
+    fn rebuild_dedupe(&mut self, dedupe: &Option<DedupeConfig>) {
+        self.dedupe = dedupe.as_ref().map(|config| {
+            let mut lsh = LshIndex::new(config.threshold);
+            for (item, embedding) in self.embeddings.rows().enumerate() {
+                lsh.insert(item, embedding);
+            }
+            lsh
+        });
+    }
+
+    fn rebuild_binary(&mut self, quantization: &Option<QuantizationConfig>) {
+        self.binary = quantization.as_ref().map(|_| self.binary_codes());
+    }
+
+    fn binary_codes(&self) -> BinaryCodes {
+        let mut codes = BinaryCodes::default();
+        for embedding in self.embeddings.rows() {
+            codes.push(embedding);
+        }
+
+        codes
+    }
+
+    fn push(&mut self, text_body: TextBody, embedding: sbert::Embeddings) -> Result<(), String> {
+        self.embeddings.owned_mut()?.extend(&[embedding])?;
+        if let Some(lsh) = &mut self.dedupe {
+            lsh.insert(self.texts.len(), self.embeddings.row(self.texts.len()));
+        }
+        if let Some(codes) = &mut self.binary {
+            codes.push(self.embeddings.row(self.texts.len()));
+        }
+
+        self.texts.push(text_body);
+
+        Ok(())
+    }
+
+    // Embeddings from the model, reduced if the index is.
+    fn prepare(&self, embedding: &[f32]) -> Result<Embeddings, String> {
+        match &self.reduction {
+            Some(reduction) => reduction.reduce(embedding),
+            None => Ok(embedding.to_vec()),
+        }
+    }
+
+    fn prepare_all(&self, embeddings: &[Embeddings]) -> Result<Vec<Embeddings>, String> {
+        embeddings
+            .iter()
+            .map(|embedding| self.prepare(embedding))
+            .collect()
+    }
+
+    fn check_query(&self, query: &[f32]) -> Result<(), String> {
+        let dim = self.embeddings.dimensions();
+        if !self.texts.is_empty() && query.len() != dim {
+            return Err(format!(
+                "Vectors not equal length (a={}, b={dim})",
+                query.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn fit_reduction(&self, dims: usize, sample: usize, seed: u64) -> Result<Reduction, String> {
+        if self.reduction.is_some() {
+            return Err(String::from("Index is already reduced"));
+        }
+
+        let (_, vectors) = self.sample_rows(sample, seed);
+        Reduction::fit(&vectors, dims, seed)
+    }
+
+    // Copies a random sample of the stored embeddings, so that analyses can
+    // run on it without holding the index lock.
+    fn sample_rows(&self, sample: usize, seed: u64) -> (Vec<usize>, Vec<Vec<f32>>) {
+        let positions = projection::sample_positions(self.embeddings.len(), sample, seed);
+        let vectors = positions
+            .iter()
+            .map(|position| self.embeddings.row(*position).to_vec())
+            .collect();
+
+        (positions, vectors)
+    }
+
+    fn slice(&self, start: usize, end: usize) -> (Vec<TextBody>, Vec<Embeddings>) {
+        let end = end.min(self.texts.len());
+        let start = start.min(end);
+
+        (
+            self.texts[start..end].to_vec(),
+            (start..end)
+                .map(|row| self.embeddings.row(row).to_vec())
+                .collect(),
+        )
+    }
+
+    // Exact cosine search for a prepared query.
+    fn knn(&self, query: &[f32], results: usize) -> Result<Vec<IndexWithScore>, String> {
+        sent_transform::search_knn_matrix(
+            query,
+            self.embeddings.as_slice(),
+            self.embeddings.dimensions(),
+            results,
+        )
+    }
+
+    fn quantized(
+        &self,
+        codes: &BinaryCodes,
+        query: &[f32],
+        results: usize,
+        oversample: f32,
+    ) -> Result<Vec<IndexWithScore>, String> {
+        self.check_query(query)?;
+
+        let candidates = codes.nearest(query, (results as f32 * oversample).ceil() as usize);
+
+        Ok(
+            sent_transform::top_k_rows(candidates.len(), results, |candidate| {
+                matrix::dot(query, self.embeddings.row(candidates[candidate]))
+            })
+            .into_iter()
+            .map(|raw_result| IndexWithScore {
+                index: candidates[raw_result.index],
+                score: raw_result.score,
+            })
+            .collect(),
+        )
+    }
+
+    fn nearest_duplicate(&self, embedding: &[f32], threshold: f32) -> Option<(usize, f32)> {
+        self.dedupe
+            .as_ref()?
+            .candidates(embedding)
+            .into_iter()
+            .map(|item| (item, matrix::dot(embedding, self.embeddings.row(item))))
+            .filter(|(_, score)| *score >= threshold)
+            .max_by(|x, y| x.1.total_cmp(&y.1))
+    }
 }
 
 pub struct GuardedIndex {
     index: sync::RwLock<Index>,
+    settings: IndexSettings,
+    pipeline: Pipeline,
+}
+
+fn search_results(texts: &[TextBody], raw_results: &[IndexWithScore]) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = raw_results
+        .iter()
+        .map(|raw_result| {
+            let text_body = &texts[raw_result.index];
+
+            SearchResult {
+                id: String::from(&text_body.id),
+                text: String::from(&text_body.text),
+                score: raw_result.score,
+                parent: text_body.parent.clone(),
+            }
+        })
+        .collect();
+
+    results.sort_by(|x, y| y.cmp(x)); // Sort Vector in descending order
+
+    results
 }
 
 fn err_mesg_unequal_lens<T>(texts_len: usize, embeddings_len: usize) -> Result<T, String> {
@@ -69,14 +483,101 @@ impl GuardedIndex {
         }
 
         Ok(GuardedIndex {
-            index: sync::RwLock::new(Index { texts, embeddings }),
+            index: sync::RwLock::new(Index {
+                texts,
+                embeddings: Storage::Owned(Matrix::from_rows(embeddings)?),
+                dedupe: None,
+                binary: None,
+                reduction: None,
+                generation: 0,
+            }),
+            settings: IndexSettings::default(),
+            pipeline: Pipeline::identity(),
         })
     }
 
+    // A read-only index over memory-mapped embeddings, which are expected to
+    // be normalized already.
+    pub fn mapped(texts: Vec<TextBody>, embeddings: MappedMatrix) -> Result<GuardedIndex, String> {
+        if texts.len() != embeddings.len() {
+            return err_mesg_unequal_lens(texts.len(), embeddings.len());
+        }
+
+        Ok(GuardedIndex {
+            index: sync::RwLock::new(Index {
+                texts,
+                embeddings: Storage::Mapped(embeddings),
+                dedupe: None,
+                binary: None,
+                reduction: None,
+                generation: 0,
+            }),
+            settings: IndexSettings::default(),
+            pipeline: Pipeline::identity(),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.index.read().unwrap().embeddings, Storage::Mapped(_))
+    }
+
     pub fn empty() -> GuardedIndex {
         GuardedIndex::new(vec![], vec![]).unwrap()
     }
 
+    pub fn with_settings(mut self, settings: IndexSettings) -> Result<GuardedIndex, String> {
+        if let Some(dedupe) = &settings.dedupe {
+            if dedupe.threshold <= 0.0 || dedupe.threshold > 1.0 {
+                return Err(format!(
+                    "Invalid dedupe threshold {}. Must be in (0, 1]",
+                    dedupe.threshold
+                ));
+            }
+        }
+
+        if let Some(quantization) = &settings.quantization {
+            validate_oversample(quantization.oversample)?;
+        }
+
+        self.pipeline = Pipeline::new(settings.preprocess.clone())?;
+        let idx = self.index.get_mut().unwrap();
+        idx.rebuild_dedupe(&settings.dedupe);
+        idx.rebuild_binary(&settings.quantization);
+        self.settings = settings;
+
+        Ok(self)
+    }
+
+    // Restores the reduction of an index whose stored embeddings were already
+    // reduced by it.
+    pub fn with_reduction(mut self, reduction: Option<Reduction>) -> Result<GuardedIndex, String> {
+        let idx = self.index.get_mut().unwrap();
+        if let Some(reduction) = &reduction {
+            if idx.embeddings.len() > 0 && idx.embeddings.dimensions() != reduction.dimensions() {
+                return Err(format!(
+                    "Embeddings have dimension {} but the reduction has {}",
+                    idx.embeddings.dimensions(),
+                    reduction.dimensions()
+                ));
+            }
+        }
+        idx.reduction = reduction;
+
+        Ok(self)
+    }
+
+    pub fn reduction(&self) -> Option<Reduction> {
+        self.index.read().unwrap().reduction.clone()
+    }
+
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
     #[allow(dead_code)]
     pub fn replace_contents(
         &self,
@@ -88,27 +589,80 @@ impl GuardedIndex {
         }
 
         let mut idx = self.index.write().unwrap();
+        let embeddings = idx.prepare_all(&embeddings)?;
+        *idx.embeddings.owned_mut()? = Matrix::from_rows(embeddings)?;
         idx.texts = texts;
-        idx.embeddings = embeddings;
+        idx.rebuild_dedupe(&self.settings.dedupe);
+        idx.rebuild_binary(&self.settings.quantization);
+        idx.generation += 1;
 
         Ok(())
     }
 
-    #[allow(dead_code)]
+    // With deduplication enabled, incoming entries within the threshold of an
+    // existing entry (or of an earlier incoming one) are either dropped or
+    // replace the text and embedding of that entry, which keeps its id.
     pub fn append_contents(
         &self,
         texts: &mut Vec<TextBody>,
         embeddings: &mut Vec<sbert::Embeddings>,
-    ) -> Result<(), String> {
+    ) -> Result<Vec<Deduplicated>, String> {
         if texts.len() != embeddings.len() {
             return err_mesg_unequal_lens(texts.len(), embeddings.len());
         }
 
         let mut idx = self.index.write().unwrap();
-        idx.texts.append(texts);
-        idx.embeddings.append(embeddings);
+        if idx.reduction.is_some() {
+            *embeddings = idx.prepare_all(embeddings)?;
+        }
+        idx.embeddings.owned_mut()?.validate(embeddings)?;
+        let dedupe = match &self.settings.dedupe {
+            Some(dedupe) => dedupe,
+            None => {
+                idx.embeddings.owned_mut()?.extend(embeddings)?;
+                if let Some(codes) = &mut idx.binary {
+                    for embedding in embeddings.iter() {
+                        codes.push(embedding);
+                    }
+                }
+                embeddings.clear();
+                idx.texts.append(texts);
 
-        Ok(())
+                return Ok(vec![]);
+            }
+        };
+
+        let mut deduplicated: Vec<Deduplicated> = vec![];
+        for (text_body, embedding) in texts.drain(..).zip(embeddings.drain(..)) {
+            match idx.nearest_duplicate(&embedding, dedupe.threshold) {
+                Some((existing, score)) => {
+                    deduplicated.push(Deduplicated {
+                        id: text_body.id,
+                        duplicate_of: idx.texts[existing].id.clone(),
+                        score,
+                    });
+
+                    if dedupe.mode == DedupeMode::Merge {
+                        let idx = &mut *idx;
+                        if let Some(lsh) = &mut idx.dedupe {
+                            lsh.remove(existing, idx.embeddings.row(existing));
+                        }
+                        idx.embeddings.owned_mut()?.set_row(existing, &embedding)?;
+                        if let Some(lsh) = &mut idx.dedupe {
+                            lsh.insert(existing, idx.embeddings.row(existing));
+                        }
+                        if let Some(codes) = &mut idx.binary {
+                            codes.set_row(existing, idx.embeddings.row(existing));
+                        }
+                        idx.texts[existing].text = text_body.text;
+                        idx.generation += 1;
+                    }
+                }
+                None => idx.push(text_body, embedding)?,
+            }
+        }
+
+        Ok(deduplicated)
     }
 
     #[allow(dead_code)]
@@ -118,7 +672,63 @@ impl GuardedIndex {
 
     #[allow(dead_code)]
     pub fn embeddings(&self) -> Vec<Embeddings> {
-        self.index.read().unwrap().embeddings.to_vec()
+        let idx = self.index.read().unwrap();
+        idx.embeddings.rows().map(|row| row.to_vec()).collect()
+    }
+
+    pub fn embeddings_by_id(&self, ids: &[&str]) -> Result<Vec<Embeddings>, String> {
+        let idx = self.index.read().unwrap();
+        let positions: HashMap<&str, usize> = idx
+            .texts
+            .iter()
+            .enumerate()
+            .map(|(position, text_body)| (text_body.id.as_str(), position))
+            .collect();
+
+        ids.iter()
+            .map(|id| {
+                positions
+                    .get(id)
+                    .map(|position| {
+                        let embedding = idx.embeddings.row(*position);
+                        match &idx.reduction {
+                            Some(reduction) => reduction.reconstruct(embedding),
+                            None => embedding.to_vec(),
+                        }
+                    })
+                    .ok_or_else(|| format!("{id} not found"))
+            })
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn dimensions(&self) -> usize {
+        self.index.read().unwrap().embeddings.dimensions()
+    }
+
+    pub fn slice(&self, start: usize, end: usize) -> (Vec<TextBody>, Vec<Embeddings>) {
+        self.index.read().unwrap().slice(start, end)
+    }
+
+    // The generation, number of entries and dimensions, read under one lock.
+    pub fn shape(&self) -> (u64, usize, usize) {
+        let idx = self.index.read().unwrap();
+        (idx.generation, idx.texts.len(), idx.embeddings.dimensions())
+    }
+
+    // Like `slice`, but fails if existing entries changed since `generation`.
+    pub fn slice_at(
+        &self,
+        generation: u64,
+        start: usize,
+        end: usize,
+    ) -> Result<(Vec<TextBody>, Vec<Embeddings>), String> {
+        let idx = self.index.read().unwrap();
+        if idx.generation != generation {
+            return Err(String::from("Index was modified while it was being read"));
+        }
+
+        Ok(idx.slice(start, end))
     }
 
     pub fn len(&self) -> usize {
@@ -130,6 +740,21 @@ impl GuardedIndex {
         self.index.read().unwrap().texts.is_empty()
     }
 
+    // The keys, ids or parents of entries, that match no entry of the index.
+    pub fn missing_keys<'a>(&self, keys: &[&'a str]) -> Vec<&'a str> {
+        let idx = self.index.read().unwrap();
+        let known: HashSet<&str> = idx
+            .texts
+            .iter()
+            .flat_map(|text| std::iter::once(text.id.as_str()).chain(text.parent.as_deref()))
+            .collect();
+
+        keys.iter()
+            .copied()
+            .filter(|key| !known.contains(key))
+            .collect()
+    }
+
     pub fn search_knn(
         &self,
         query: &sbert::Embeddings,
@@ -139,27 +764,34 @@ impl GuardedIndex {
             .read()
             .map_err(|_| String::from("search_knn: Failed to acquire lock"))
             .and_then(|idx| {
-                sent_transform::search_knn(query, &idx.embeddings, results).map(|raw_results| {
-                    let mut results: Vec<SearchResult> = raw_results
-                        .iter()
-                        .map(|raw_result| {
-                            let text_body = &idx.texts[raw_result.index];
-
-                            SearchResult {
-                                id: String::from(&text_body.id),
-                                text: String::from(&text_body.text),
-                                score: raw_result.score,
-                            }
-                        })
-                        .collect();
-
-                    results.sort_by(|x, y| y.cmp(x)); // Sort Vector in descending order
-
-                    results
-                })
+                idx.knn(&idx.prepare(query)?, results)
+                    .map(|raw_results| search_results(&idx.texts, &raw_results))
             })
     }
 
+    // Cosine search over the sign-bit codes of a quantized index: the nearest
+    // `oversample * results` codes by Hamming distance are rescored exactly.
+    pub fn search_quantized(
+        &self,
+        query: &sbert::Embeddings,
+        results: usize,
+        oversample: f32,
+    ) -> Result<Vec<SearchResult>, String> {
+        validate_oversample(oversample)?;
+
+        let idx = self
+            .index
+            .read()
+            .map_err(|_| String::from("search_quantized: Failed to acquire lock"))?;
+        let codes = idx
+            .binary
+            .as_ref()
+            .ok_or_else(|| String::from("Index is not quantized"))?;
+
+        idx.quantized(codes, &idx.prepare(query)?, results, oversample)
+            .map(|raw_results| search_results(&idx.texts, &raw_results))
+    }
+
     pub fn search_exemplar_svm(
         &self,
         query: &sbert::Embeddings,
@@ -169,33 +801,376 @@ impl GuardedIndex {
             .read()
             .map_err(|_| String::from("search_exemplar_svm: Failed to acquire lock"))
             .and_then(|idx| {
-                sent_transform::search_exemplar_svm(query, &idx.embeddings, results).map(
-                    |raw_results| {
-                        let mut results: Vec<SearchResult> = raw_results
-                            .iter()
-                            .map(|raw_result| {
-                                let text_body = &idx.texts[raw_result.index];
+                sent_transform::search_exemplar_svm(
+                    &idx.prepare(query)?,
+                    &idx.embeddings.row_slices(),
+                    results,
+                )
+                .map(|raw_results| search_results(&idx.texts, &raw_results))
+            })
+    }
+
+    pub fn kmeans(&self, count: ClusterCount, params: &KMeansParams) -> Result<Clustering, String> {
+        let idx = self
+            .index
+            .read()
+            .map_err(|_| String::from("kmeans: Failed to acquire lock"))?;
+
+        let embeddings = idx.embeddings.row_slices();
+
+        let (k, k_scores, result) = match count {
+            ClusterCount::Fixed(k) => (k, vec![], cluster::kmeans(&embeddings, k, params)?),
+            ClusterCount::Auto { min, max } => cluster::select_k(
+                &embeddings,
+                min,
+                max,
+                cluster::SILHOUETTE_SAMPLE_SIZE,
+                params.seed,
+                |k| cluster::kmeans(&embeddings, k, params),
+                |result| &result.assignments,
+            )?,
+        };
+
+        let sizes = cluster::cluster_sizes(&result.assignments, k);
+        let representatives =
+            cluster::representatives(&embeddings, &result.centroids, &result.assignments);
+
+        let clusters = result
+            .centroids
+            .into_iter()
+            .enumerate()
+            .map(|(cluster, centroid)| ClusterSummary {
+                cluster,
+                size: sizes[cluster],
+                centroid,
+                representative: representatives[cluster].map(|member| idx.texts[member].clone()),
+            })
+            .collect();
+
+        Ok(Clustering {
+            k,
+            iterations: result.iterations,
+            inertia: result.inertia,
+            clusters,
+            assignments: cluster_assignments(&result.assignments, &idx.texts),
+            k_scores,
+        })
+    }
+
+    pub fn hierarchy(
+        &self,
+        linkage: Linkage,
+        count: Option<ClusterCount>,
+        seed: u64,
+    ) -> Result<Hierarchy, String> {
+        let idx = self
+            .index
+            .read()
+            .map_err(|_| String::from("hierarchy: Failed to acquire lock"))?;
+
+        let embeddings = idx.embeddings.row_slices();
+        let n = embeddings.len();
+        let merges = cluster::agglomerative(&embeddings, linkage)?;
+
+        let (k, k_scores, cut) = match count {
+            None => (None, vec![], vec![]),
+            Some(ClusterCount::Fixed(k)) => {
+                if k == 0 || k > n {
+                    return Err(format!("Cannot create {k} clusters from {n} embeddings"));
+                }
+                (Some(k), vec![], cluster::cut_dendrogram(&merges, n, k))
+            }
+            Some(ClusterCount::Auto { min, max }) => cluster::select_k(
+                &embeddings,
+                min,
+                max,
+                cluster::SILHOUETTE_SAMPLE_SIZE,
+                seed,
+                |k| Ok(cluster::cut_dendrogram(&merges, n, k)),
+                |cut| cut.as_slice(),
+            )
+            .map(|(k, k_scores, cut)| (Some(k), k_scores, cut))?,
+        };
+
+        let assignments = cluster_assignments(&cut, &idx.texts);
+
+        Ok(Hierarchy {
+            leaves: idx
+                .texts
+                .iter()
+                .map(|text_body| text_body.id.clone())
+                .collect(),
+            merges,
+            k,
+            assignments,
+            k_scores,
+        })
+    }
+
+    pub fn duplicates(&self, threshold: f32) -> Result<Vec<DuplicateGroup>, String> {
+        self.index
+            .read()
+            .map_err(|_| String::from("duplicates: Failed to acquire lock"))
+            .map(|idx| {
+                lsh::duplicate_groups(&idx.embeddings.row_slices(), threshold)
+                    .into_iter()
+                    .map(|group| DuplicateGroup {
+                        canonical: idx.texts[group.canonical].clone(),
+                        duplicates: group
+                            .duplicates
+                            .into_iter()
+                            .map(|(member, score)| {
+                                let text_body = &idx.texts[member];
 
                                 SearchResult {
                                     id: String::from(&text_body.id),
                                     text: String::from(&text_body.text),
-                                    score: raw_result.score,
+                                    score,
+                                    parent: text_body.parent.clone(),
                                 }
                             })
-                            .collect();
+                            .collect(),
+                    })
+                    .collect()
+            })
+    }
 
-                        results.sort_by(|x, y| y.cmp(x)); // Sort Vector in descending order
+    pub fn anomalies(
+        &self,
+        method: AnomalyMethod,
+        params: &KMeansParams,
+        results: usize,
+    ) -> Result<Vec<SearchResult>, String> {
+        let idx = self
+            .index
+            .read()
+            .map_err(|_| String::from("anomalies: Failed to acquire lock"))?;
 
-                        results
-                    },
-                )
+        let scores = anomaly::score(&idx.embeddings.row_slices(), method, params)?;
+
+        Ok(anomaly::top_anomalies(&scores, results)
+            .into_iter()
+            .map(|(member, score)| {
+                let text_body = &idx.texts[member];
+
+                SearchResult {
+                    id: String::from(&text_body.id),
+                    text: String::from(&text_body.text),
+                    score,
+                    parent: text_body.parent.clone(),
+                }
             })
+            .collect())
+    }
+
+    // Fits a PCA with `dims` components on a sample of the embeddings,
+    // without changing the index.
+    pub fn fit_reduction(
+        &self,
+        dims: usize,
+        sample: usize,
+        seed: u64,
+    ) -> Result<Reduction, String> {
+        // Power iteration over the sample is slow, so it runs after the sample
+        // is copied out and the lock released.
+        let vectors = {
+            let idx = self
+                .index
+                .read()
+                .map_err(|_| String::from("fit_reduction: Failed to acquire lock"))?;
+            if idx.reduction.is_some() {
+                return Err(String::from("Index is already reduced"));
+            }
+
+            idx.sample_rows(sample, seed).1
+        };
+
+        Reduction::fit(&vectors, dims, seed)
+    }
+
+    // Replaces every embedding by its reduced vector. Appended embeddings and
+    // queries are reduced from then on.
+    pub fn apply_reduction(&self, reduction: Reduction) -> Result<(), String> {
+        let mut idx = self
+            .index
+            .write()
+            .map_err(|_| String::from("apply_reduction: Failed to acquire lock"))?;
+        if idx.reduction.is_some() {
+            return Err(String::from("Index is already reduced"));
+        }
+
+        let reduced = idx
+            .embeddings
+            .rows()
+            .map(|row| reduction.reduce(row))
+            .collect::<Result<Vec<Embeddings>, String>>()?;
+        *idx.embeddings.owned_mut()? = Matrix::from_rows(reduced)?;
+        idx.reduction = Some(reduction);
+        idx.rebuild_dedupe(&self.settings.dedupe);
+        idx.rebuild_binary(&self.settings.quantization);
+        idx.generation += 1;
+
+        Ok(())
+    }
+
+    // Compares an approximate search method with exact search, using a sample
+    // of the stored embeddings as queries. Each query's own entry is left out
+    // of both result sets. Indices that are not quantized are evaluated with
+    // codes built for the evaluation.
+    pub fn evaluate_recall(
+        &self,
+        method: ApproximateMethod,
+        k: usize,
+        queries: usize,
+        seed: u64,
+    ) -> Result<RecallReport, String> {
+        let idx = self
+            .index
+            .read()
+            .map_err(|_| String::from("evaluate_recall: Failed to acquire lock"))?;
+        if idx.texts.is_empty() {
+            return Err(String::from("Cannot evaluate an empty index"));
+        }
+        if k == 0 || queries == 0 {
+            return Err(String::from("k and queries must be at least 1"));
+        }
+
+        let built;
+        let approximation = match method {
+            ApproximateMethod::Binary { oversample } => {
+                validate_oversample(oversample)?;
+                let codes = match &idx.binary {
+                    Some(codes) => codes,
+                    None => {
+                        built = idx.binary_codes();
+                        &built
+                    }
+                };
+                Approximation::Binary { codes, oversample }
+            }
+            // The reduction is fitted as on a dry run and applied to a copy,
+            // so the stored embeddings stay the exact baseline.
+            ApproximateMethod::Pca { dims, sample } => {
+                let reduction = idx.fit_reduction(dims, sample, seed)?;
+                let reduced = Matrix::from_rows(
+                    idx.embeddings
+                        .rows()
+                        .map(|row| reduction.reduce(row))
+                        .collect::<Result<Vec<Embeddings>, String>>()?,
+                )?;
+                Approximation::Reduced { reduction, reduced }
+            }
+        };
+
+        let positions = projection::sample_positions(idx.texts.len(), queries, seed);
+        let others = |raw_results: Vec<IndexWithScore>, position: usize| -> Vec<usize> {
+            raw_results
+                .into_iter()
+                .map(|raw_result| raw_result.index)
+                .filter(|index| *index != position)
+                .take(k)
+                .collect()
+        };
+
+        let mut recalls: Vec<f32> = vec![];
+        let mut exact_ms: Vec<f64> = vec![];
+        let mut approximate_ms: Vec<f64> = vec![];
+        for position in positions {
+            let query = idx.embeddings.row(position);
+
+            let start = Instant::now();
+            let exact = idx.knn(query, k + 1)?;
+            exact_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+
+            let start = Instant::now();
+            let approximate = match &approximation {
+                Approximation::Binary { codes, oversample } => {
+                    idx.quantized(codes, query, k + 1, *oversample)?
+                }
+                Approximation::Reduced { reduction, reduced } => sent_transform::search_knn_matrix(
+                    &reduction.reduce(query)?,
+                    reduced.as_slice(),
+                    reduced.dimensions(),
+                    k + 1,
+                )?,
+            };
+            approximate_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+
+            recalls.push(evaluation::recall(
+                &others(exact, position),
+                &others(approximate, position),
+            ));
+        }
+
+        Ok(RecallReport {
+            method: method.name(),
+            k,
+            queries: recalls.len(),
+            recall: recalls.iter().sum::<f32>() / recalls.len() as f32,
+            min_recall: recalls.iter().copied().fold(1.0, f32::min),
+            exact_latency_ms: Latency::from_samples(&exact_ms),
+            approximate_latency_ms: Latency::from_samples(&approximate_ms),
+        })
+    }
+
+    pub fn projection(
+        &self,
+        dims: usize,
+        method: ProjectionMethod,
+        sample: usize,
+        seed: u64,
+    ) -> Result<Projection, String> {
+        // t-SNE can take a while, so the sample and its ids are copied out
+        // and the lock is released before projecting.
+        let (total, ids, vectors) = {
+            let idx = self
+                .index
+                .read()
+                .map_err(|_| String::from("projection: Failed to acquire lock"))?;
+
+            let (positions, vectors) = idx.sample_rows(sample, seed);
+            let ids: Vec<String> = positions
+                .iter()
+                .map(|position| idx.texts[*position].id.clone())
+                .collect();
+
+            (idx.embeddings.len(), ids, vectors)
+        };
+
+        let coords = projection::project(&vectors, dims, method)?;
+
+        Ok(Projection {
+            dims,
+            total,
+            sampled: ids.len() < total,
+            points: ids
+                .into_iter()
+                .zip(coords)
+                .map(|(id, coords)| ProjectedPoint { id, coords })
+                .collect(),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mapped::MappedStore;
+
+    fn text_body(id: impl ToString) -> TextBody {
+        TextBody {
+            id: id.to_string(),
+            text: id.to_string(),
+            parent: None,
+        }
+    }
+
+    fn temp_store(name: &str) -> MappedStore {
+        let dir = std::env::temp_dir().join(format!("scout-vector-index-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        MappedStore::open(&dir.to_string_lossy()).expect("Could not open store")
+    }
 
     #[test]
     fn test_guarded_index() {
@@ -204,6 +1179,7 @@ mod tests {
         let texts = vec![TextBody {
             id: "id".to_string(),
             text: "text".to_string(),
+            parent: None,
         }];
 
         let embeddings: Vec<sbert::Embeddings> = vec![vec![1.0, 0.0]];
@@ -224,6 +1200,7 @@ mod tests {
         let mut new_texts = vec![TextBody {
             id: "id-new".to_string(),
             text: "text-new".to_string(),
+            parent: None,
         }];
 
         let mut new_embeddings = vec![vec![0.0, 1.0]];
@@ -234,4 +1211,289 @@ mod tests {
 
         assert_eq!(index.len(), 2);
     }
+
+    #[test]
+    fn test_dedupe_on_append() {
+        for (mode, expected_text) in [(DedupeMode::Skip, "a"), (DedupeMode::Merge, "a2")] {
+            let index = GuardedIndex::empty()
+                .with_settings(IndexSettings {
+                    dedupe: Some(DedupeConfig {
+                        threshold: 0.99,
+                        mode,
+                    }),
+                    ..IndexSettings::default()
+                })
+                .expect("Could not create index");
+
+            let deduplicated = index
+                .append_contents(
+                    &mut vec![text_body("a"), text_body("b"), text_body("a2")],
+                    &mut vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0]],
+                )
+                .expect("Could not append");
+
+            assert_eq!(index.len(), 2);
+            assert_eq!(index.texts()[0].text, expected_text);
+            assert_eq!(deduplicated.len(), 1);
+            assert_eq!(
+                (
+                    deduplicated[0].id.as_str(),
+                    deduplicated[0].duplicate_of.as_str()
+                ),
+                ("a2", "a")
+            );
+        }
+
+        assert!(GuardedIndex::empty()
+            .with_settings(IndexSettings {
+                dedupe: Some(DedupeConfig {
+                    threshold: 1.5,
+                    mode: DedupeMode::Skip,
+                }),
+                ..IndexSettings::default()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_mapped_index_is_read_only() {
+        let store = temp_store("read-only");
+
+        store
+            .write(
+                "mapped",
+                &IndexSettings::default(),
+                None,
+                &[text_body("a"), text_body("b")],
+                &[vec![1.0, 0.0], vec![0.0, 1.0]],
+            )
+            .expect("Could not write");
+
+        let (_, _, texts, embeddings) = store.read("mapped").expect("Could not read");
+        let index = GuardedIndex::mapped(texts, embeddings).expect("Could not map");
+        assert!(index.is_read_only());
+
+        let results = index
+            .search_knn(&vec![0.0, 1.0], 1)
+            .expect("Could not search_knn");
+        assert_eq!(results[0].id, "b");
+        assert_eq!(
+            index.embeddings_by_id(&["a"]).unwrap(),
+            vec![vec![1.0, 0.0]]
+        );
+
+        assert!(index
+            .append_contents(&mut vec![text_body("c")], &mut vec![vec![1.0, 0.0]])
+            .is_err());
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn test_search_quantized() {
+        let index = GuardedIndex::empty()
+            .with_settings(IndexSettings {
+                quantization: Some(QuantizationConfig { oversample: 2.0 }),
+                ..IndexSettings::default()
+            })
+            .expect("Could not create index");
+        index
+            .append_contents(
+                &mut vec![text_body("a"), text_body("b"), text_body("c")],
+                &mut vec![
+                    sent_transform::l2_normalize(vec![1.0, 0.2, 0.1]),
+                    sent_transform::l2_normalize(vec![-1.0, -0.5, 0.3]),
+                    sent_transform::l2_normalize(vec![0.8, 0.6, 0.1]),
+                ],
+            )
+            .expect("Could not append");
+
+        let query = sent_transform::l2_normalize(vec![0.9, 0.3, 0.1]);
+        let exact = index.search_knn(&query, 2).expect("Could not search_knn");
+        let quantized = index
+            .search_quantized(&query, 2, 2.0)
+            .expect("Could not search_quantized");
+        assert_eq!(quantized, exact);
+
+        assert!(index.search_quantized(&query, 2, 0.5).is_err());
+        assert!(index.search_quantized(&vec![1.0], 2, 2.0).is_err());
+        assert!(GuardedIndex::empty()
+            .search_quantized(&query, 2, 2.0)
+            .is_err());
+        assert!(GuardedIndex::empty()
+            .with_settings(IndexSettings {
+                quantization: Some(QuantizationConfig { oversample: 0.0 }),
+                ..IndexSettings::default()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_reduce() {
+        let embedding = |i: usize| {
+            let angle = i as f32 * 0.4;
+            sent_transform::l2_normalize(vec![angle.cos(), angle.sin(), 0.01 * i as f32, 0.0])
+        };
+
+        let index = GuardedIndex::new(
+            (0..12).map(text_body).collect(),
+            (0..12).map(embedding).collect(),
+        )
+        .expect("Could not create index");
+        let exact = index.search_knn(&embedding(3), 3).unwrap();
+
+        let reduction = index.fit_reduction(2, 100, 0).expect("Could not fit");
+        assert_eq!(index.dimensions(), 4);
+        let (generation, _, _) = index.shape();
+        index.apply_reduction(reduction).expect("Could not reduce");
+        assert_eq!(index.dimensions(), 2);
+        assert!(index.slice_at(generation, 0, 1).is_err());
+        assert!(index.fit_reduction(2, 100, 0).is_err());
+
+        let reduced = index.search_knn(&embedding(3), 3).unwrap();
+        assert_eq!(reduced[0].id, exact[0].id);
+
+        index
+            .append_contents(&mut vec![text_body(12)], &mut vec![embedding(12)])
+            .expect("Could not append");
+        assert_eq!(index.dimensions(), 2);
+        assert_eq!(index.search_knn(&embedding(12), 1).unwrap()[0].id, "12");
+
+        let restored = index.embeddings_by_id(&["5"]).unwrap();
+        assert!(matrix::dot(&restored[0], &embedding(5)) > 0.95);
+
+        let (generation, rows, _) = index.shape();
+        let (texts, embeddings) = index.slice_at(generation, 0, rows).unwrap();
+        let copy = GuardedIndex::new(texts, embeddings)
+            .and_then(|copy| copy.with_reduction(index.reduction()))
+            .expect("Could not restore");
+        assert_eq!(copy.search_knn(&embedding(3), 1).unwrap()[0].id, "3");
+        assert!(GuardedIndex::new(vec![text_body(0)], vec![embedding(0)])
+            .and_then(|copy| copy.with_reduction(index.reduction()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_evaluate_recall() {
+        let texts: Vec<TextBody> = (0..50)
+            .map(|i| TextBody {
+                id: i.to_string(),
+                text: i.to_string(),
+                parent: None,
+            })
+            .collect();
+        let embeddings: Vec<Embeddings> = (0..50)
+            .map(|i| {
+                let x = i as f32;
+                sent_transform::l2_normalize(vec![x.sin(), x.cos(), (0.3 * x).sin(), 0.5])
+            })
+            .collect();
+        let index = GuardedIndex::new(texts, embeddings).expect("Could not create index");
+
+        let report = index
+            .evaluate_recall(ApproximateMethod::Binary { oversample: 50.0 }, 5, 10, 0)
+            .expect("Could not evaluate");
+        assert_eq!(report.method, "binary");
+        assert_eq!(report.queries, 10);
+        assert_eq!(report.recall, 1.0);
+        assert!(report.approximate_latency_ms.p50 <= report.approximate_latency_ms.max);
+
+        let report = index
+            .evaluate_recall(ApproximateMethod::Binary { oversample: 1.0 }, 5, 50, 0)
+            .expect("Could not evaluate");
+        assert!(report.min_recall <= report.recall);
+
+        let method = ApproximateMethod::Pca {
+            dims: 4,
+            sample: 50,
+        };
+        let report = index
+            .evaluate_recall(method, 5, 10, 0)
+            .expect("Could not evaluate");
+        assert_eq!(report.method, "pca");
+        assert!((report.recall - 1.0).abs() < 1e-6);
+        assert!(
+            index
+                .evaluate_recall(
+                    ApproximateMethod::Pca {
+                        dims: 1,
+                        sample: 50
+                    },
+                    5,
+                    10,
+                    0
+                )
+                .expect("Could not evaluate")
+                .recall
+                < 1.0
+        );
+
+        assert!(index
+            .evaluate_recall(ApproximateMethod::Binary { oversample: 4.0 }, 5, 0, 0)
+            .is_err());
+        assert!(GuardedIndex::empty()
+            .evaluate_recall(ApproximateMethod::Binary { oversample: 4.0 }, 5, 10, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_collapse_by_parent() {
+        let result = |id: &str, score: f32, parent: Option<&str>| SearchResult {
+            id: id.to_string(),
+            text: id.to_string(),
+            score,
+            parent: parent.map(String::from),
+        };
+
+        let results = vec![
+            result("doc#1", 0.9, Some("doc")),
+            result("other", 0.8, None),
+            result("doc#0", 0.7, Some("doc")),
+            result("memo#0", 0.6, Some("memo")),
+        ];
+
+        let ids: Vec<String> = collapse_by_parent(results, 3)
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+
+        assert_eq!(ids, vec!["doc#1", "other", "memo#0"]);
+    }
+
+    #[test]
+    fn test_search_collapsed_widens() {
+        // Ten chunks of one document rank above a second document.
+        let ranked = |nsearch: usize| -> Vec<SearchResult> {
+            (0..nsearch.min(11))
+                .map(|i| SearchResult {
+                    id: if i < 10 {
+                        format!("doc#{i}")
+                    } else {
+                        String::from("other")
+                    },
+                    text: String::new(),
+                    score: 1.0 - i as f32 * 0.01,
+                    parent: if i < 10 {
+                        Some(String::from("doc"))
+                    } else {
+                        None
+                    },
+                })
+                .collect()
+        };
+
+        let mut searched: Vec<usize> = vec![];
+        let results = search_collapsed(2, 11, |nsearch| {
+            searched.push(nsearch);
+            Ok(ranked(nsearch))
+        })
+        .expect("Could not search");
+
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["doc#0", "other"]);
+        assert_eq!(searched, vec![8, 11]);
+
+        let results =
+            search_collapsed(3, 11, |nsearch| Ok(ranked(nsearch))).expect("Could not search");
+        assert_eq!(results.len(), 2);
+    }
 }